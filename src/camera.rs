@@ -1,5 +1,5 @@
 use crate::animation::WSequenced;
-use crate::math::{f32x4, RandomSample2d, Vec2, Vec2u, Wec2, Wec3};
+use crate::math::{f32x4, RandomSample2d, Vec2, Vec2u, Wat3, Wec2, Wec3};
 use crate::ray::WRay;
 
 pub trait Camera: Send + Sync {
@@ -16,6 +16,68 @@ pub trait Camera: Send + Sync {
     /// gets the pixel radius size (half-width) at some t value (distance) from the camera
     /// assumes that the distance is along a ray emitted from the camera.
     fn half_pixel_size_at(&self, t: f32x4) -> f32x4;
+
+    /// Projects world space points back onto the film as seen by the camera at `time`, giving
+    /// the uv coordinates (the same ones passed to `get_rays`) they would be seen at.
+    fn project(&self, point: Wec3, time: f32x4) -> Wec2;
+
+    /// Picks a point on the lens at `time` the same way `get_rays` does with `samples`, to connect
+    /// points in the scene to the camera through.
+    fn sample_lens(&self, time: f32x4, samples: &[f32x4; 2]) -> Wec3;
+
+    /// How the camera at `time` sees `point` through `lens_point`, for tracing light from the
+    /// scene back to the film: returns (the uv coordinates `point` is seen at, the importance of
+    /// the connection). The importance is how densely `get_rays` traces rays through `lens_point`
+    /// in the direction of `point`, per unit uv area and solid angle, so the light arriving from
+    /// `point` contributes to `uv` weighted by it. It is zero where the camera can't see `point`,
+    /// and everywhere for cameras whose rays through a point on the lens all go the same way.
+    fn importance(&self, lens_point: Wec3, point: Wec3, time: f32x4) -> (Wec2, f32x4);
+
+    /// The camera's right, up and backward axes in world space at `time`, as the columns of the
+    /// rotation from camera space to world space.
+    fn basis(&self, time: f32x4) -> Wat3;
+}
+
+/// The basis of a camera at `origin` looking at `at`, see `Camera::basis`.
+fn look_at_basis(origin: Wec3, at: Wec3, up: Wec3) -> Wat3 {
+    let basis_w = (origin - at).normalized();
+    let basis_u = up.cross(basis_w).normalized();
+    let basis_v = basis_w.cross(basis_u);
+    Wat3::new(basis_u, basis_v, basis_w)
+}
+
+/// Projects points through a perspective camera whose rays at uv `(0, 0)` and `(1, 1)` point
+/// towards `-w -/+ u * half_size.x -/+ v * half_size.y`.
+fn project_perspective(origin: Wec3, at: Wec3, up: Wec3, half_size: Wec2, point: Wec3) -> Wec2 {
+    let basis_w = (origin - at).normalized();
+    let basis_u = up.cross(basis_w).normalized();
+    let basis_v = basis_w.cross(basis_u);
+
+    let d = point - origin;
+    let z = -d.dot(basis_w);
+    let half = f32x4::from(0.5);
+    Wec2::new(
+        (d.dot(basis_u) / (z * half_size.x) + f32x4::ONE) * half,
+        (d.dot(basis_v) / (z * half_size.y) + f32x4::ONE) * half,
+    )
+}
+
+/// The importance of a perspective camera seeing a point at `uv` in a direction at angle `theta`
+/// to its axis, see `Camera::importance`. The film spans `2 * half_size` at distance 1, so around
+/// that direction a unit of uv area covers `4 * half_size.x * half_size.y * cos^3(theta)`
+/// steradians.
+fn perspective_importance(half_size: Wec2, cos_theta: f32x4, uv: Wec2) -> f32x4 {
+    let on_film = cos_theta.cmp_gt(f32x4::ZERO)
+        & uv.x.cmp_ge(f32x4::ZERO)
+        & uv.x.cmp_le(f32x4::ONE)
+        & uv.y.cmp_ge(f32x4::ZERO)
+        & uv.y.cmp_le(f32x4::ONE);
+    let film_area = f32x4::from(4.0) * half_size.x * half_size.y;
+    f32x4::merge(
+        on_film,
+        f32x4::ONE / (film_area * cos_theta * cos_theta * cos_theta),
+        f32x4::ZERO,
+    )
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -38,6 +100,12 @@ impl CameraStore {
     }
 }
 
+impl Default for CameraStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct PinholeCamera<O, A, U> {
     half_size: Wec2,
@@ -116,6 +184,38 @@ where
     fn half_pixel_size_at(&self, t: f32x4) -> f32x4 {
         self.half_pixel_size * t
     }
+
+    fn project(&self, point: Wec3, time: f32x4) -> Wec2 {
+        project_perspective(
+            self.origin.sample_at(time),
+            self.at.sample_at(time),
+            self.up.sample_at(time),
+            self.half_size,
+            point,
+        )
+    }
+
+    fn sample_lens(&self, time: f32x4, _samples: &[f32x4; 2]) -> Wec3 {
+        self.origin.sample_at(time)
+    }
+
+    fn importance(&self, _lens_point: Wec3, point: Wec3, time: f32x4) -> (Wec2, f32x4) {
+        let origin = self.origin.sample_at(time);
+        let at = self.at.sample_at(time);
+        let uv = project_perspective(origin, at, self.up.sample_at(time), self.half_size, point);
+
+        let d = point - origin;
+        let cos_theta = d.dot(at - origin) / (d.mag() * (at - origin).mag());
+        (uv, perspective_importance(self.half_size, cos_theta, uv))
+    }
+
+    fn basis(&self, time: f32x4) -> Wat3 {
+        look_at_basis(
+            self.origin.sample_at(time),
+            self.at.sample_at(time),
+            self.up.sample_at(time),
+        )
+    }
 }
 #[derive(Clone, Copy)]
 pub struct ThinLensCamera<A, O, LA, U, F> {
@@ -210,6 +310,58 @@ where
     fn half_pixel_size_at(&self, t: f32x4) -> f32x4 {
         self.half_pixel_size * t
     }
+
+    /// Projects through the center of the lens, i.e. ignores defocus.
+    fn project(&self, point: Wec3, time: f32x4) -> Wec2 {
+        project_perspective(
+            self.origin.sample_at(time),
+            self.at.sample_at(time),
+            self.up.sample_at(time),
+            self.half_size,
+            point,
+        )
+    }
+
+    fn sample_lens(&self, time: f32x4, samples: &[f32x4; 2]) -> Wec3 {
+        let origin = self.origin.sample_at(time);
+        let basis_w = (origin - self.at.sample_at(time)).normalized();
+        let basis_u = self.up.sample_at(time).cross(basis_w).normalized();
+        let basis_v = basis_w.cross(basis_u);
+
+        let rd = Wec2::rand_in_unit_disk(samples) * self.aperture.sample_at(time);
+        origin + basis_u * rd.x + basis_v * rd.y
+    }
+
+    /// The film position is where the ray from `lens_point` to `point` crosses the focus plane,
+    /// projected through the center of the lens.
+    fn importance(&self, lens_point: Wec3, point: Wec3, time: f32x4) -> (Wec2, f32x4) {
+        let origin = self.origin.sample_at(time);
+        let at = self.at.sample_at(time);
+        let focus_dist = (self.focus.sample_at(time) - origin).mag();
+        let axis = (at - origin).normalized();
+
+        let d = point - lens_point;
+        let z = d.dot(axis);
+        let on_focus_plane = lens_point + d * (focus_dist / z);
+        let uv = project_perspective(
+            origin,
+            at,
+            self.up.sample_at(time),
+            self.half_size,
+            on_focus_plane,
+        );
+
+        let cos_theta = z / d.mag();
+        (uv, perspective_importance(self.half_size, cos_theta, uv))
+    }
+
+    fn basis(&self, time: f32x4) -> Wat3 {
+        look_at_basis(
+            self.origin.sample_at(time),
+            self.at.sample_at(time),
+            self.up.sample_at(time),
+        )
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -282,4 +434,39 @@ where
     fn half_pixel_size_at(&self, _t: f32x4) -> f32x4 {
         self.half_pixel_size
     }
+
+    fn project(&self, point: Wec3, time: f32x4) -> Wec2 {
+        let origin = self.origin.sample_at(time);
+        let at = self.at.sample_at(time);
+        let up = self.up.sample_at(time);
+
+        let basis_w = (at - origin).normalized();
+        let basis_u = basis_w.cross(up).normalized();
+        let basis_v = basis_u.cross(basis_w);
+
+        let d = point - origin;
+        Wec2::new(
+            (d.dot(basis_u) + self.half_size.x) / self.full_size.x,
+            (d.dot(basis_v) + self.half_size.y) / self.full_size.y,
+        )
+    }
+
+    /// Orthographic cameras have no lens, every ray leaves the film along the camera's axis.
+    fn sample_lens(&self, time: f32x4, _samples: &[f32x4; 2]) -> Wec3 {
+        self.origin.sample_at(time)
+    }
+
+    /// Light can only reach an orthographic camera along its axis, so connections to it never
+    /// carry any importance.
+    fn importance(&self, _lens_point: Wec3, point: Wec3, time: f32x4) -> (Wec2, f32x4) {
+        (self.project(point, time), f32x4::ZERO)
+    }
+
+    fn basis(&self, time: f32x4) -> Wat3 {
+        look_at_basis(
+            self.origin.sample_at(time),
+            self.at.sample_at(time),
+            self.up.sample_at(time),
+        )
+    }
 }
@@ -0,0 +1,712 @@
+use clap::{App, Arg, ArgMatches};
+
+use rayn::color::{ColorSpace, OutputTransform};
+use rayn::denoise::DenoiseSettings;
+use rayn::film::{ChannelKind, CropWindow, ExrPrecision, OutputFormat};
+use rayn::integrator::DebugView;
+use rayn::math::{Extent2u, Vec2, Vec2u};
+use rayn::postprocess::{Bloom, Glare};
+use rayn::tonemap::{DisplayTransform, ToneMapOperator};
+
+use std::ops::Range;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    BlackmanHarris,
+    MitchellNetravali,
+    Box,
+    LanczosSinc,
+}
+
+impl FilterKind {
+    const NAMES: &'static [&'static str] = &["blackman-harris", "mitchell", "box", "lanczos"];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "blackman-harris" => Some(FilterKind::BlackmanHarris),
+            "mitchell" => Some(FilterKind::MitchellNetravali),
+            "box" => Some(FilterKind::Box),
+            "lanczos" => Some(FilterKind::LanczosSinc),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegratorKind {
+    PathTracing,
+    Bidirectional,
+    LightTracing,
+    AmbientOcclusion,
+}
+
+impl IntegratorKind {
+    const NAMES: &'static [&'static str] = &["path", "bdpt", "light", "ao"];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "path" => Some(IntegratorKind::PathTracing),
+            "bdpt" => Some(IntegratorKind::Bidirectional),
+            "light" => Some(IntegratorKind::LightTracing),
+            "ao" => Some(IntegratorKind::AmbientOcclusion),
+            _ => None,
+        }
+    }
+}
+
+/// Options given on the command line. Render settings which are `None` were not given and
+/// should be taken from the scene instead.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub scene: Option<PathBuf>,
+    pub output_dir: PathBuf,
+    pub resolution: Option<Extent2u>,
+    /// Only render the pixels inside this window.
+    pub crop: Option<CropWindow>,
+    /// Write only the pixels inside the crop window instead of the full frame.
+    pub write_cropped: bool,
+    pub samples: Option<usize>,
+    /// Sample adaptively until this relative error is reached, using `samples` as the maximum.
+    pub adaptive: Option<f32>,
+    /// The samples every pixel gets in each adaptive pass.
+    pub pass_samples: usize,
+    /// The samples every pixel gets before adaptive sampling can consider it converged.
+    pub min_samples: usize,
+    /// The number of progressive passes the samples of each frame are split into, if given.
+    pub passes: Option<usize>,
+    /// Write a preview after a progressive pass once this many seconds have passed since the last
+    /// one, or after every pass if zero.
+    pub preview_interval: Option<f32>,
+    /// Write a checkpoint after a progressive pass once this many seconds have passed since the
+    /// last one.
+    pub checkpoint_interval: Option<f32>,
+    /// Continue each frame from its checkpoint if there is one.
+    pub resume: bool,
+    pub max_bounces: Option<usize>,
+    pub volume_marches: Option<usize>,
+    pub working_space: Option<ColorSpace>,
+    /// Render spectrally, even if the scene doesn't ask to.
+    pub spectral: bool,
+    pub integrator: IntegratorKind,
+    /// The distance within which geometry occludes, for the `ao` integrator.
+    pub ao_radius: f32,
+    /// The number of occlusion rays per camera sample, for the `ao` integrator.
+    pub ao_samples: usize,
+    /// Render this view of how tracing the scene went instead of the integrator's image.
+    pub debug: Option<DebugView>,
+    pub frames: Range<usize>,
+    pub frame_rate: f32,
+    pub shutter_speed: f32,
+    pub filter: FilterKind,
+    /// `None` means the default radius for the chosen filter.
+    pub filter_radius: Option<f32>,
+    pub tile_size: Extent2u,
+    pub threads: usize,
+    pub write_channels: Vec<(ChannelKind, OutputFormat)>,
+    /// Also write every channel into a single multi-layer OpenEXR file with this precision.
+    pub exr: Option<ExrPrecision>,
+    /// How color is mapped to display values in PNGs.
+    pub display: DisplayTransform,
+    /// Denoise the color before writing it.
+    pub denoise: Option<DenoiseSettings>,
+    /// Effects applied to the color after denoising and before writing it.
+    pub bloom: Option<Bloom>,
+    pub glare: Option<Glare>,
+}
+
+impl Options {
+    pub fn from_args() -> Self {
+        let app = App::new("rayn")
+            .version(clap::crate_version!())
+            .about("A CPU path tracer focused on rendering SDFs, specifically fractals.")
+            .arg(
+                Arg::with_name("scene")
+                    .help("The RON scene file to render. Renders the scene in setup.rs if omitted.")
+                    .index(1),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .help("The directory to write rendered images into")
+                    .short("o")
+                    .long("output")
+                    .value_name("DIR")
+                    .default_value("renders"),
+            )
+            .arg(
+                Arg::with_name("resolution")
+                    .help("The resolution of the output image, overriding the scene's")
+                    .short("r")
+                    .long("resolution")
+                    .value_name("WxH")
+                    .validator(|s| parse_extent(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("crop")
+                    .help("Only render the pixels from X0,Y0 (inclusive) to X1,Y1 (exclusive), counted from the top left")
+                    .long("crop")
+                    .value_name("X0,Y0,X1,Y1")
+                    .conflicts_with("crop-ndc")
+                    .validator(|s| parse_crop_pixels(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("crop-ndc")
+                    .help("Only render the window from X0,Y0 to X1,Y1 as fractions of the image size, from 0,0 at the top left to 1,1 at the bottom right")
+                    .long("crop-ndc")
+                    .value_name("X0,Y0,X1,Y1")
+                    .validator(|s| parse_crop_ndc(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("write-cropped")
+                    .help("Write only the crop window instead of the full frame. OpenEXR files keep the full frame as their display window.")
+                    .long("write-cropped"),
+            )
+            .arg(
+                Arg::with_name("samples")
+                    .help("Samples per pixel (multiplied by 4), overriding the scene's")
+                    .short("s")
+                    .long("samples")
+                    .value_name("N")
+                    .validator(|s| parse_nonzero(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("adaptive")
+                    .help("Render in passes, only adding samples to pixels whose relative error is above this threshold, e.g. `0.02`. The sample count becomes the maximum per pixel.")
+                    .long("adaptive")
+                    .value_name("ERROR")
+                    .validator(|s| parse_positive(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("pass-samples")
+                    .help("Samples per pixel (multiplied by 4) in each adaptive pass after the first")
+                    .long("pass-samples")
+                    .value_name("N")
+                    .default_value("1")
+                    .validator(|s| parse_nonzero(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("min-samples")
+                    .help("Samples per pixel (multiplied by 4) every pixel gets before adaptive sampling can consider it converged")
+                    .long("min-samples")
+                    .value_name("N")
+                    .default_value("4")
+                    .validator(|s| parse_nonzero(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("passes")
+                    .help("Split the samples of each frame into this many progressive passes over the whole image [default: 1, or one per sample with --checkpoint]")
+                    .long("passes")
+                    .value_name("N")
+                    .validator(|s| parse_nonzero(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("preview-interval")
+                    .help("Write preview images after a pass once this many seconds have passed since the last preview, or after every pass if 0")
+                    .long("preview-interval")
+                    .value_name("SECONDS")
+                    .validator(|s| parse_non_negative(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("checkpoint")
+                    .help("Write a checkpoint of the render after a pass once this many seconds have passed since the last one")
+                    .long("checkpoint")
+                    .value_name("SECONDS")
+                    .validator(|s| parse_non_negative(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("resume")
+                    .help("Continue rendering each frame from its checkpoint in the output directory, if there is one")
+                    .long("resume"),
+            )
+            .arg(
+                Arg::with_name("bounces")
+                    .help("The maximum number of indirect bounces, overriding the scene's")
+                    .short("b")
+                    .long("bounces")
+                    .value_name("N")
+                    .validator(|s| parse_number::<usize>(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("volume-marches")
+                    .help("The number of volume scattering samples along each ray, overriding the scene's")
+                    .long("volume-marches")
+                    .value_name("N")
+                    .validator(|s| parse_number::<usize>(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("working-space")
+                    .help("The linear color space to render in, overriding the scene's")
+                    .long("working-space")
+                    .value_name("SPACE")
+                    .possible_values(ColorSpace::NAMES)
+                    .case_insensitive(true),
+            )
+            .arg(
+                Arg::with_name("spectral")
+                    .help("Render with sampled wavelengths instead of RGB")
+                    .long("spectral"),
+            )
+            .arg(
+                Arg::with_name("integrator")
+                    .help("How light is traced: from the camera with `path`, from both the camera and the lights with `bdpt`, or only from the lights with `light`; `ao` renders ambient occlusion instead")
+                    .long("integrator")
+                    .value_name("INTEGRATOR")
+                    .possible_values(IntegratorKind::NAMES)
+                    .case_insensitive(true)
+                    .default_value("path"),
+            )
+            .arg(
+                Arg::with_name("ao-radius")
+                    .help("The distance within which geometry occludes, with `--integrator ao`")
+                    .long("ao-radius")
+                    .value_name("DISTANCE")
+                    .default_value("1")
+                    .validator(|s| parse_positive(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("ao-samples")
+                    .help("The number of occlusion rays traced per camera sample, with `--integrator ao`")
+                    .long("ao-samples")
+                    .value_name("N")
+                    .default_value("4")
+                    .validator(|s| parse_nonzero(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("debug")
+                    .help("Render a view of how tracing went instead of the image: march steps, hits, distance to the surface where marching stopped, camera space normals, path bounces or NaN/infinite samples")
+                    .long("debug")
+                    .value_name("VIEW")
+                    .possible_values(DebugView::NAMES)
+                    .case_insensitive(true),
+            )
+            .arg(
+                Arg::with_name("frames")
+                    .help("The frame or range of frames to render, e.g. `12` or `0..24`")
+                    .short("f")
+                    .long("frames")
+                    .value_name("RANGE")
+                    .default_value("1..2")
+                    .validator(|s| parse_frames(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("frame-rate")
+                    .help("Frames per second, used to find the start time of each frame")
+                    .long("frame-rate")
+                    .value_name("FPS")
+                    .default_value("24")
+                    .validator(|s| parse_positive(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("shutter")
+                    .help("How long the shutter is open for each frame, in seconds")
+                    .long("shutter")
+                    .value_name("SECONDS")
+                    .default_value("0.041666668")
+                    .validator(|s| parse_non_negative(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("filter")
+                    .help("The pixel reconstruction filter")
+                    .long("filter")
+                    .value_name("FILTER")
+                    .possible_values(FilterKind::NAMES)
+                    .case_insensitive(true)
+                    .default_value("blackman-harris"),
+            )
+            .arg(
+                Arg::with_name("filter-radius")
+                    .help("The radius of the reconstruction filter, in pixels")
+                    .long("filter-radius")
+                    .value_name("PIXELS")
+                    .validator(|s| parse_positive(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("tile-size")
+                    .help("The size of the tiles the image is split into, e.g. `16` or `32x16`")
+                    .long("tile-size")
+                    .value_name("WxH")
+                    .default_value("16x16")
+                    .validator(|s| parse_extent(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("threads")
+                    .help("The number of render threads [default: number of logical cpus]")
+                    .short("j")
+                    .long("threads")
+                    .value_name("N")
+                    .validator(|s| parse_nonzero(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("channels")
+                    .help("Which channels to write and optionally in which format (png, hdr or pfm), e.g. `color:hdr,alpha,worldnormal`")
+                    .short("c")
+                    .long("channels")
+                    .value_name("CHANNELS")
+                    .use_delimiter(true)
+                    .default_value("alpha,worldnormal,color")
+                    .validator(|s| parse_channel(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("exr")
+                    .help("Also write all channels as layers of a linear, unclamped OpenEXR file")
+                    .long("exr"),
+            )
+            .arg(
+                Arg::with_name("exr-half")
+                    .help("Write the OpenEXR file with half instead of full float precision")
+                    .long("exr-half")
+                    .requires("exr"),
+            )
+            .arg(
+                Arg::with_name("exposure")
+                    .help("Exposure adjustment in stops applied before tone mapping")
+                    .long("exposure")
+                    .value_name("EV")
+                    .default_value("0")
+                    .allow_hyphen_values(true)
+                    .validator(|s| parse_number::<f32>(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("tonemap")
+                    .help("The tone mapping operator used for PNG color output")
+                    .long("tonemap")
+                    .value_name("OPERATOR")
+                    .possible_values(ToneMapOperator::NAMES)
+                    .case_insensitive(true)
+                    .default_value("clamp"),
+            )
+            .arg(
+                Arg::with_name("white")
+                    .help("The white point of the reinhard-extended operator [default: 4]")
+                    .long("white")
+                    .value_name("WHITE")
+                    .validator(|s| parse_positive(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("output-transform")
+                    .help("How PNG color output is encoded for the display. rec2020-pq is HDR and isn't tone mapped")
+                    .long("output-transform")
+                    .value_name("TRANSFORM")
+                    .possible_values(OutputTransform::NAMES)
+                    .case_insensitive(true)
+                    .default_value("srgb"),
+            )
+            .arg(
+                Arg::with_name("denoise")
+                    .help("Denoise the color before writing it, guided by the normal, albedo and depth of the first hits")
+                    .long("denoise"),
+            )
+            .arg(
+                Arg::with_name("denoise-iterations")
+                    .help("The number of denoising passes, each of which doubles the filter's reach [default: 5]")
+                    .long("denoise-iterations")
+                    .value_name("N")
+                    .requires("denoise")
+                    .validator(|s| parse_nonzero(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("bloom")
+                    .help("Spread this fraction of the energy of highlights into a glow around them, e.g. `0.1`")
+                    .long("bloom")
+                    .value_name("INTENSITY")
+                    .validator(|s| parse_positive(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("bloom-radius")
+                    .help("The radius in pixels of the tightest part of the bloom [default: 2]")
+                    .long("bloom-radius")
+                    .value_name("PIXELS")
+                    .requires("bloom")
+                    .validator(|s| parse_positive(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("glare")
+                    .help("Spread this fraction of the energy of highlights into star shaped streaks, e.g. `0.05`")
+                    .long("glare")
+                    .value_name("INTENSITY")
+                    .validator(|s| parse_positive(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("glare-streaks")
+                    .help("The number of streaks of the glare star [default: 6]")
+                    .long("glare-streaks")
+                    .value_name("N")
+                    .requires("glare")
+                    .validator(|s| parse_nonzero(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("glare-length")
+                    .help("The length of the glare streaks in pixels [default: 64]")
+                    .long("glare-length")
+                    .value_name("PIXELS")
+                    .requires("glare")
+                    .validator(|s| parse_positive(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("highlight-threshold")
+                    .help("The brightness above which pixels contribute to bloom and glare")
+                    .long("highlight-threshold")
+                    .value_name("VALUE")
+                    .default_value("1")
+                    .validator(|s| parse_non_negative(&s).map(|_| ())),
+            );
+
+        Self::from_matches(&app.get_matches())
+    }
+
+    // All values have already been checked by their validators, so parsing them can't fail.
+    fn from_matches(matches: &ArgMatches) -> Self {
+        Options {
+            scene: matches.value_of_os("scene").map(PathBuf::from),
+            output_dir: PathBuf::from(matches.value_of("output").unwrap()),
+            resolution: matches
+                .value_of("resolution")
+                .map(|s| parse_extent(s).unwrap()),
+            crop: matches
+                .value_of("crop")
+                .map(|s| parse_crop_pixels(s).unwrap())
+                .or_else(|| {
+                    matches
+                        .value_of("crop-ndc")
+                        .map(|s| parse_crop_ndc(s).unwrap())
+                }),
+            write_cropped: matches.is_present("write-cropped"),
+            samples: matches
+                .value_of("samples")
+                .map(|s| parse_nonzero(s).unwrap()),
+            adaptive: matches
+                .value_of("adaptive")
+                .map(|s| parse_positive(s).unwrap()),
+            pass_samples: parse_nonzero(matches.value_of("pass-samples").unwrap()).unwrap(),
+            min_samples: parse_nonzero(matches.value_of("min-samples").unwrap()).unwrap(),
+            passes: matches
+                .value_of("passes")
+                .map(|s| parse_nonzero(s).unwrap()),
+            preview_interval: matches
+                .value_of("preview-interval")
+                .map(|s| parse_non_negative(s).unwrap()),
+            checkpoint_interval: matches
+                .value_of("checkpoint")
+                .map(|s| parse_non_negative(s).unwrap()),
+            resume: matches.is_present("resume"),
+            max_bounces: matches
+                .value_of("bounces")
+                .map(|s| parse_number(s).unwrap()),
+            volume_marches: matches
+                .value_of("volume-marches")
+                .map(|s| parse_number(s).unwrap()),
+            working_space: matches
+                .value_of("working-space")
+                .map(|s| ColorSpace::from_name(s).unwrap()),
+            spectral: matches.is_present("spectral"),
+            integrator: IntegratorKind::from_name(matches.value_of("integrator").unwrap()).unwrap(),
+            ao_radius: parse_positive(matches.value_of("ao-radius").unwrap()).unwrap(),
+            ao_samples: parse_nonzero(matches.value_of("ao-samples").unwrap()).unwrap(),
+            debug: matches
+                .value_of("debug")
+                .map(|s| DebugView::from_name(s).unwrap()),
+            frames: parse_frames(matches.value_of("frames").unwrap()).unwrap(),
+            frame_rate: parse_positive(matches.value_of("frame-rate").unwrap()).unwrap(),
+            shutter_speed: parse_non_negative(matches.value_of("shutter").unwrap()).unwrap(),
+            filter: FilterKind::from_name(matches.value_of("filter").unwrap()).unwrap(),
+            filter_radius: matches
+                .value_of("filter-radius")
+                .map(|s| parse_positive(s).unwrap()),
+            tile_size: parse_extent(matches.value_of("tile-size").unwrap()).unwrap(),
+            threads: matches
+                .value_of("threads")
+                .map(|s| parse_nonzero(s).unwrap())
+                .unwrap_or_else(num_cpus::get),
+            write_channels: matches
+                .values_of("channels")
+                .unwrap()
+                .map(|s| parse_channel(s).unwrap())
+                .collect(),
+            exr: if !matches.is_present("exr") {
+                None
+            } else if matches.is_present("exr-half") {
+                Some(ExrPrecision::Half)
+            } else {
+                Some(ExrPrecision::Float)
+            },
+            display: DisplayTransform {
+                exposure: parse_number(matches.value_of("exposure").unwrap()).unwrap(),
+                operator: match ToneMapOperator::from_name(matches.value_of("tonemap").unwrap()) {
+                    Some(ToneMapOperator::ExtendedReinhard { white }) => {
+                        ToneMapOperator::ExtendedReinhard {
+                            white: matches
+                                .value_of("white")
+                                .map_or(white, |s| parse_positive(s).unwrap()),
+                        }
+                    }
+                    operator => operator.unwrap(),
+                },
+                output: OutputTransform::from_name(matches.value_of("output-transform").unwrap())
+                    .unwrap(),
+            },
+            denoise: if matches.is_present("denoise") {
+                let defaults = DenoiseSettings::default();
+                Some(DenoiseSettings {
+                    iterations: matches
+                        .value_of("denoise-iterations")
+                        .map_or(defaults.iterations, |s| parse_nonzero(s).unwrap()),
+                    ..defaults
+                })
+            } else {
+                None
+            },
+            bloom: matches.value_of("bloom").map(|s| {
+                let defaults = Bloom::default();
+                Bloom {
+                    threshold: parse_non_negative(matches.value_of("highlight-threshold").unwrap())
+                        .unwrap(),
+                    intensity: parse_positive(s).unwrap(),
+                    radius: matches
+                        .value_of("bloom-radius")
+                        .map_or(defaults.radius, |s| parse_positive(s).unwrap()),
+                    ..defaults
+                }
+            }),
+            glare: matches.value_of("glare").map(|s| {
+                let defaults = Glare::default();
+                Glare {
+                    threshold: parse_non_negative(matches.value_of("highlight-threshold").unwrap())
+                        .unwrap(),
+                    intensity: parse_positive(s).unwrap(),
+                    streaks: matches
+                        .value_of("glare-streaks")
+                        .map_or(defaults.streaks, |s| parse_nonzero(s).unwrap()),
+                    length: matches
+                        .value_of("glare-length")
+                        .map_or(defaults.length, |s| parse_positive(s).unwrap()),
+                    ..defaults
+                }
+            }),
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.trim()
+        .parse()
+        .map_err(|_| format!("`{}` is not a valid number", s))
+}
+
+fn parse_nonzero(s: &str) -> Result<usize, String> {
+    match parse_number(s)? {
+        0 => Err(String::from("must be at least 1")),
+        n => Ok(n),
+    }
+}
+
+fn parse_positive(s: &str) -> Result<f32, String> {
+    let x: f32 = parse_number(s)?;
+    if x > 0.0 {
+        Ok(x)
+    } else {
+        Err(format!("`{}` must be positive", s))
+    }
+}
+
+fn parse_non_negative(s: &str) -> Result<f32, String> {
+    let x: f32 = parse_number(s)?;
+    if x >= 0.0 {
+        Ok(x)
+    } else {
+        Err(format!("`{}` must not be negative", s))
+    }
+}
+
+/// Parses either `WxH` or a single number for a square extent.
+fn parse_extent(s: &str) -> Result<Extent2u, String> {
+    let mut parts = s.split(|c| c == 'x' || c == 'X');
+    let w = parts.next().map(parse_nonzero).unwrap()?;
+    let h = parts.next().map(parse_nonzero).unwrap_or(Ok(w))?;
+    if parts.next().is_some() {
+        return Err(format!("`{}` is not of the form WxH", s));
+    }
+    Ok(Extent2u::new(w as u32, h as u32))
+}
+
+/// Parses the corners `X0,Y0,X1,Y1` of a non-empty window.
+fn parse_corners<T>(s: &str) -> Result<[T; 4], String>
+where
+    T: std::str::FromStr + PartialOrd + Copy,
+{
+    let values = s
+        .split(',')
+        .map(parse_number)
+        .collect::<Result<Vec<T>, _>>()?;
+    match values[..] {
+        [x0, y0, x1, y1] if x0 < x1 && y0 < y1 => Ok([x0, y0, x1, y1]),
+        [_, _, _, _] => Err(format!("`{}` must have X0 < X1 and Y0 < Y1", s)),
+        _ => Err(format!("`{}` is not of the form X0,Y0,X1,Y1", s)),
+    }
+}
+
+fn parse_crop_pixels(s: &str) -> Result<CropWindow, String> {
+    let [x0, y0, x1, y1] = parse_corners::<u32>(s)?;
+    Ok(CropWindow::Pixels {
+        min: Vec2u::new(x0, y0),
+        max: Vec2u::new(x1, y1),
+    })
+}
+
+fn parse_crop_ndc(s: &str) -> Result<CropWindow, String> {
+    let [x0, y0, x1, y1] = parse_corners::<f32>(s)?;
+    if x0 < 0.0 || y0 < 0.0 || x1 > 1.0 || y1 > 1.0 {
+        return Err(format!("`{}` must be between 0 and 1", s));
+    }
+    Ok(CropWindow::Ndc {
+        min: Vec2::new(x0, y0),
+        max: Vec2::new(x1, y1),
+    })
+}
+
+/// Parses either a single frame `N` or an exclusive range of frames `START..END`.
+fn parse_frames(s: &str) -> Result<Range<usize>, String> {
+    let range = match s.find("..") {
+        Some(idx) => parse_number(&s[..idx])?..parse_number(&s[idx + 2..])?,
+        None => {
+            let frame = parse_number(s)?;
+            frame..frame + 1
+        }
+    };
+    if range.start >= range.end {
+        Err(format!("the frame range `{}` is empty", s))
+    } else {
+        Ok(range)
+    }
+}
+
+/// Parses a channel name optionally followed by an output format, e.g. `color` or `color:hdr`.
+/// Channels are written as PNG if no format is given.
+fn parse_channel(s: &str) -> Result<(ChannelKind, OutputFormat), String> {
+    let mut parts = s.trim().splitn(2, ':');
+    let kind = parse_channel_kind(parts.next().unwrap())?;
+    let format = match parts.next() {
+        Some(name) => OutputFormat::from_name(name.trim()).ok_or_else(|| {
+            format!(
+                "unknown output format `{}` (possible formats: {})",
+                name,
+                OutputFormat::NAMES.join(", ")
+            )
+        })?,
+        None => OutputFormat::Png,
+    };
+    Ok((kind, format))
+}
+
+fn parse_channel_kind(s: &str) -> Result<ChannelKind, String> {
+    ChannelKind::from_name(s.trim()).ok_or_else(|| {
+        format!(
+            "unknown channel `{}` (possible channels: {})",
+            s,
+            ChannelKind::all()
+                .iter()
+                .map(|k| k.name().to_ascii_lowercase())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    })
+}
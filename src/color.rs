@@ -0,0 +1,233 @@
+//! Color spaces: the linear RGB spaces colors can be rendered and specified in, and the output
+//! transforms which encode rendered colors for a display.
+
+use serde::Deserialize;
+
+use crate::math::{Mat3, Vec3};
+use crate::tonemap::srgb_oetf;
+
+/// Builds a matrix from its rows, which is how color conversion matrices are usually written.
+fn rows(r0: [f32; 3], r1: [f32; 3], r2: [f32; 3]) -> Mat3 {
+    Mat3::new(
+        Vec3::new(r0[0], r1[0], r2[0]),
+        Vec3::new(r0[1], r1[1], r2[1]),
+        Vec3::new(r0[2], r1[2], r2[2]),
+    )
+}
+
+/// A linear RGB color space, defined by its primaries and white point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum ColorSpace {
+    /// The primaries of sRGB and Rec.709 with a D65 white point, without the sRGB transfer
+    /// function. This is what colors always were before color spaces existed.
+    LinearSrgb,
+    /// The AP1 primaries of ACES with the ACES white point, a wide gamut space designed for
+    /// rendering.
+    AcesCg,
+    /// The Rec.2020 (UHDTV) primaries with a D65 white point.
+    Rec2020,
+    /// The Display P3 primaries with a D65 white point.
+    DisplayP3,
+}
+
+impl Default for ColorSpace {
+    fn default() -> Self {
+        ColorSpace::LinearSrgb
+    }
+}
+
+impl ColorSpace {
+    pub const NAMES: &'static [&'static str] = &["srgb", "acescg", "rec2020", "p3"];
+
+    /// Looks up a color space by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "srgb" => Some(ColorSpace::LinearSrgb),
+            "acescg" => Some(ColorSpace::AcesCg),
+            "rec2020" => Some(ColorSpace::Rec2020),
+            "p3" => Some(ColorSpace::DisplayP3),
+            _ => None,
+        }
+    }
+
+    /// The CIE xy chromaticities of the red, green and blue primaries and the white point.
+    pub fn chromaticities(self) -> [(f32, f32); 4] {
+        match self {
+            ColorSpace::LinearSrgb => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), D65],
+            ColorSpace::AcesCg => [(0.713, 0.293), (0.165, 0.830), (0.128, 0.044), ACES_WHITE],
+            ColorSpace::Rec2020 => [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046), D65],
+            ColorSpace::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060), D65],
+        }
+    }
+
+    fn is_d65(self) -> bool {
+        self != ColorSpace::AcesCg
+    }
+
+    /// The matrix from this space to CIE XYZ, relative to the space's own white point.
+    fn rgb_to_xyz(self) -> Mat3 {
+        match self {
+            ColorSpace::LinearSrgb => rows(
+                [0.412456, 0.357576, 0.180438],
+                [0.212673, 0.715152, 0.072175],
+                [0.019334, 0.119192, 0.950304],
+            ),
+            ColorSpace::AcesCg => rows(
+                [0.662454, 0.134004, 0.156188],
+                [0.272229, 0.674082, 0.053690],
+                [-0.005575, 0.004061, 1.010339],
+            ),
+            ColorSpace::Rec2020 => rows(
+                [0.636958, 0.144617, 0.168881],
+                [0.262700, 0.677998, 0.059302],
+                [0.0, 0.028073, 1.060985],
+            ),
+            ColorSpace::DisplayP3 => rows(
+                [0.486571, 0.265668, 0.198217],
+                [0.228975, 0.691739, 0.079287],
+                [0.0, 0.045113, 1.043944],
+            ),
+        }
+    }
+
+    /// The matrix from CIE XYZ to this space, relative to the space's own white point.
+    fn xyz_to_rgb(self) -> Mat3 {
+        match self {
+            ColorSpace::LinearSrgb => rows(
+                [3.240454, -1.537139, -0.498531],
+                [-0.969266, 1.876011, 0.041556],
+                [0.055643, -0.204026, 1.057225],
+            ),
+            ColorSpace::AcesCg => rows(
+                [1.641023, -0.324803, -0.236425],
+                [-0.663663, 1.615332, 0.016756],
+                [0.011722, -0.008284, 0.988395],
+            ),
+            ColorSpace::Rec2020 => rows(
+                [1.716651, -0.355671, -0.253366],
+                [-0.666684, 1.616481, 0.015769],
+                [0.017640, -0.042771, 0.942103],
+            ),
+            ColorSpace::DisplayP3 => rows(
+                [2.493497, -0.931384, -0.402711],
+                [-0.829489, 1.762664, 0.023625],
+                [0.035846, -0.076172, 0.956885],
+            ),
+        }
+    }
+
+    /// The matrix converting colors in this space to `to`. Colors are chromatically adapted
+    /// between white points with the Bradford transform, so white stays white.
+    pub fn conversion_to(self, to: ColorSpace) -> Mat3 {
+        let adapt = match (self.is_d65(), to.is_d65()) {
+            (false, true) => rows(
+                [0.987224, -0.006113, 0.015953],
+                [-0.007598, 1.001860, 0.005330],
+                [0.003073, -0.005096, 1.081680],
+            ),
+            (true, false) => rows(
+                [1.013030, 0.006105, -0.014971],
+                [0.007698, 0.998165, -0.005032],
+                [-0.002841, 0.004685, 0.924507],
+            ),
+            _ => Mat3::identity(),
+        };
+        to.xyz_to_rgb() * adapt * self.rgb_to_xyz()
+    }
+
+    /// Convert a color in this space to `to`.
+    pub fn convert(self, color: Vec3, to: ColorSpace) -> Vec3 {
+        if self == to {
+            color
+        } else {
+            self.conversion_to(to) * color
+        }
+    }
+}
+
+const D65: (f32, f32) = (0.3127, 0.3290);
+const ACES_WHITE: (f32, f32) = (0.32168, 0.33767);
+
+/// How rendered colors are encoded for a display when written as low dynamic range images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputTransform {
+    /// sRGB primaries with the sRGB transfer function.
+    Srgb,
+    /// Display P3 primaries with the sRGB transfer function.
+    DisplayP3,
+    /// Rec.2020 primaries with the SMPTE ST 2084 (PQ) transfer function, for HDR displays. A
+    /// linear value of 1 is encoded as the 203 nit reference white of ITU-R BT.2408, and highlights
+    /// aren't tone mapped but left to the display, up to PQ's 10000 nit peak.
+    Rec2020Pq,
+}
+
+impl Default for OutputTransform {
+    fn default() -> Self {
+        OutputTransform::Srgb
+    }
+}
+
+impl OutputTransform {
+    pub const NAMES: &'static [&'static str] = &["srgb", "p3", "rec2020-pq"];
+
+    /// Looks up an output transform by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "srgb" => Some(OutputTransform::Srgb),
+            "p3" => Some(OutputTransform::DisplayP3),
+            "rec2020-pq" => Some(OutputTransform::Rec2020Pq),
+            _ => None,
+        }
+    }
+
+    /// The linear color space colors are converted into before they are encoded.
+    pub fn space(self) -> ColorSpace {
+        match self {
+            OutputTransform::Srgb => ColorSpace::LinearSrgb,
+            OutputTransform::DisplayP3 => ColorSpace::DisplayP3,
+            OutputTransform::Rec2020Pq => ColorSpace::Rec2020,
+        }
+    }
+
+    /// Whether the output can show values above 1, in which case colors are not tone mapped.
+    pub fn is_hdr(self) -> bool {
+        self == OutputTransform::Rec2020Pq
+    }
+
+    /// The ITU-T H.273 color primaries, transfer characteristics, matrix coefficients and full
+    /// range flag of the encoding, which tag PNGs with the color space they are in.
+    pub fn cicp(self) -> [u8; 4] {
+        match self {
+            OutputTransform::Srgb => [1, 13, 0, 1],
+            OutputTransform::DisplayP3 => [12, 13, 0, 1],
+            OutputTransform::Rec2020Pq => [9, 16, 0, 1],
+        }
+    }
+
+    /// Encode a linear color in `self.space()` to display values in `[0, 1]`.
+    pub fn encode(self, color: Vec3) -> Vec3 {
+        match self {
+            OutputTransform::Srgb | OutputTransform::DisplayP3 => {
+                color.map(|x| srgb_oetf(x.max(0.0).min(1.0)))
+            }
+            OutputTransform::Rec2020Pq => {
+                const REFERENCE_WHITE_NITS: f32 = 203.0;
+                const PEAK_NITS: f32 = 10000.0;
+                color.map(|x| pq_inverse_eotf(x.max(0.0) * REFERENCE_WHITE_NITS / PEAK_NITS))
+            }
+        }
+    }
+}
+
+/// The inverse of the SMPTE ST 2084 EOTF, mapping linear light relative to 10000 nits to PQ
+/// encoded values.
+fn pq_inverse_eotf(y: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+    let y_m1 = y.max(0.0).min(1.0).powf(M1);
+    ((C1 + C2 * y_m1) / (1.0 + C3 * y_m1)).powf(M2)
+}
@@ -0,0 +1,196 @@
+use rayon::prelude::*;
+
+use crate::math::{Extent2u, Vec3};
+use crate::spectrum::Srgb;
+
+/// Settings for the edge-avoiding à-trous wavelet filter used by `Film::denoise`.
+#[derive(Clone, Copy, Debug)]
+pub struct DenoiseSettings {
+    /// The number of filter passes. Each pass doubles the spacing of the kernel taps, so the
+    /// filter's footprint is about `4 * 2^iterations` pixels wide.
+    pub iterations: usize,
+    /// How many standard deviations of the pixel's estimated noise two luminances may differ by
+    /// before they stop being blended. Higher values blur more.
+    pub sigma_luminance: f32,
+    /// The exponent applied to the cosine between two normals. Higher values preserve more
+    /// geometric detail.
+    pub sigma_normal: f32,
+    /// The relative difference in depth per pixel of distance at which neighbors stop being
+    /// blended.
+    pub sigma_depth: f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            sigma_luminance: 4.0,
+            sigma_normal: 64.0,
+            sigma_depth: 0.1,
+        }
+    }
+}
+
+/// Feature buffers which guide the filter to keep edges sharp, any of which may be missing.
+pub(crate) struct Guides<'a> {
+    pub normal: Option<&'a [Vec3]>,
+    pub albedo: Option<&'a [Srgb]>,
+    pub depth: Option<&'a [f32]>,
+}
+
+/// The 1d B3 spline kernel which is applied separably at each level of the wavelet transform.
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Albedo below this is clamped when demodulating, so that black surfaces don't blow up.
+const MIN_ALBEDO: f32 = 0.01;
+
+pub(crate) fn luminance(color: Vec3) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+/// The variance of the luminance of a color whose channels have the given variances, assuming
+/// the channels are independent.
+fn luminance_variance(variance: Vec3) -> f32 {
+    0.2126 * 0.2126 * variance.x + 0.7152 * 0.7152 * variance.y + 0.0722 * 0.0722 * variance.z
+}
+
+/// Denoise `color` in place. `variance` is the variance of each channel of each pixel's mean, which
+/// tells the filter how much each pixel can be trusted.
+///
+/// The color is divided by the albedo first, so that texture detail isn't blurred along with the
+/// noise, and then each pass blends every pixel with its neighbors weighted by how similar their
+/// luminance (relative to the noise), normals and depths are, following "Edge-Avoiding À-Trous
+/// Wavelet Transform for fast Global Illumination Filtering" (Dammertz et al. 2010) and SVGF
+/// (Schied et al. 2017). The variance is filtered along with the color, so each pass trusts the
+/// increasingly smooth result more.
+pub(crate) fn denoise(
+    color: &mut [Srgb],
+    variance: &[Vec3],
+    guides: &Guides,
+    res: Extent2u,
+    settings: &DenoiseSettings,
+) {
+    let (w, h) = (res.w as usize, res.h as usize);
+
+    let albedo_at = |idx: usize| {
+        guides.albedo.map_or(Vec3::one(), |albedo| {
+            albedo[idx].0.map(|x| x.max(MIN_ALBEDO))
+        })
+    };
+
+    let mut current = (0..w * h)
+        .map(|idx| {
+            // Each channel is divided by its own albedo, and so is its noise.
+            let albedo = albedo_at(idx);
+            (
+                color[idx].0 / albedo,
+                luminance_variance(variance[idx] / (albedo * albedo)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    for iteration in 0..settings.iterations {
+        let step = 1isize << iteration;
+        let previous = &current;
+        current = (0..w * h)
+            .into_par_iter()
+            .map(|idx| filter_pixel(previous, guides, w, h, idx, step, settings))
+            .collect();
+    }
+
+    for (idx, (irradiance, _)) in current.into_iter().enumerate() {
+        color[idx] = Srgb(irradiance * albedo_at(idx));
+    }
+}
+
+/// Filter pixel `idx` for one pass of the à-trous filter, returning its color and variance.
+fn filter_pixel(
+    input: &[(Vec3, f32)],
+    guides: &Guides,
+    w: usize,
+    h: usize,
+    idx: usize,
+    step: isize,
+    settings: &DenoiseSettings,
+) -> (Vec3, f32) {
+    let (x, y) = ((idx % w) as isize, (idx / w) as isize);
+    let (color_p, _) = input[idx];
+    let luminance_p = luminance(color_p);
+
+    // The variance of a single pixel is a noisy estimate itself, so blur it slightly.
+    let mut variance_p = 0.0;
+    let mut variance_weight = 0.0;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let (qx, qy) = (x + dx, y + dy);
+            if qx < 0 || qy < 0 || qx >= w as isize || qy >= h as isize {
+                continue;
+            }
+            let weight = KERNEL[(dx + 2) as usize] * KERNEL[(dy + 2) as usize];
+            variance_p += input[qx as usize + qy as usize * w].1 * weight;
+            variance_weight += weight;
+        }
+    }
+    let sigma_luminance = settings.sigma_luminance * (variance_p / variance_weight).sqrt() + 1e-6;
+
+    let normal_p = guides.normal.map(|normal| normal[idx]);
+    let depth_p = guides.depth.map(|depth| depth[idx]);
+
+    let mut color_sum = Vec3::zero();
+    let mut variance_sum = 0.0;
+    let mut weight_sum = 0.0;
+
+    for (j, kernel_y) in KERNEL.iter().enumerate() {
+        for (i, kernel_x) in KERNEL.iter().enumerate() {
+            let (dx, dy) = ((i as isize - 2) * step, (j as isize - 2) * step);
+            let (qx, qy) = (x + dx, y + dy);
+            if qx < 0 || qy < 0 || qx >= w as isize || qy >= h as isize {
+                continue;
+            }
+            let q = qx as usize + qy as usize * w;
+            let (color_q, variance_q) = input[q];
+
+            let kernel_weight = kernel_x * kernel_y;
+            if q == idx {
+                color_sum += color_q * kernel_weight;
+                variance_sum += variance_q * kernel_weight * kernel_weight;
+                weight_sum += kernel_weight;
+                continue;
+            }
+
+            let luminance_weight =
+                (-(luminance_p - luminance(color_q)).abs() / sigma_luminance).exp();
+
+            let normal_weight = match (normal_p, guides.normal) {
+                (Some(normal_p), Some(normal)) => {
+                    normal_p.dot(normal[q]).max(0.0).powf(settings.sigma_normal)
+                }
+                _ => 1.0,
+            };
+
+            let depth_weight = match (depth_p, guides.depth) {
+                (Some(depth_p), Some(depth)) => {
+                    let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                    let scale = settings.sigma_depth * depth_p.max(depth[q]) * distance;
+                    if scale > 0.0 {
+                        (-(depth_p - depth[q]).abs() / scale).exp()
+                    } else {
+                        1.0
+                    }
+                }
+                _ => 1.0,
+            };
+
+            let weight = kernel_weight * luminance_weight * normal_weight * depth_weight;
+            color_sum += color_q * weight;
+            variance_sum += variance_q * weight * weight;
+            weight_sum += weight;
+        }
+    }
+
+    // The center tap is always fully weighted, so the sum can't be zero.
+    (
+        color_sum / weight_sum,
+        variance_sum / (weight_sum * weight_sum),
+    )
+}
@@ -4,29 +4,72 @@ use generic_array::{ArrayLength, GenericArray};
 
 use rand::prelude::*;
 
-use crate::camera::CameraHandle;
+use crate::camera::{Camera, CameraHandle};
+use crate::color::{ColorSpace, OutputTransform};
+use crate::denoise::{self, DenoiseSettings, Guides};
 use crate::filter::{Filter, FilterImportanceSampler};
-use crate::hitable::HitStore;
+use crate::hitable::{HitStore, WShadingPoint};
 use crate::integrator::Integrator;
+use crate::material::MaterialHandle;
 use crate::math::{f32x4, Bounds2u, Extent2u, Vec2, Vec2u, Vec3, Wec2};
+use crate::postprocess::PostEffect;
 use crate::ray::{Ray, WRay};
-use crate::sampler::Samples;
-use crate::spectrum::Srgb;
+use crate::sampler::{SampleLayout, Samples};
+use crate::spectrum::{self, Srgb};
+use crate::tonemap::DisplayTransform;
 use crate::world::World;
-use crate::setup::VOLUME_MARCHES_PER_SAMPLE;
 
 use std::collections::hash_map::HashMap;
+use std::io::{Read, Write};
 use std::ops::Range;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc, Mutex,
 };
 
+/// How the samples which land in a pixel are combined. `average` takes the mean of all samples,
+/// while `first` keeps the first sample recorded in the pixel and ignores the rest, which is needed
+/// for ids where averaging is meaningless. Channels using `first` must be initialized to a negative
+/// value, which then means that no sample was recorded. `variance` and `count` channels aren't
+/// sampled by integrators at all, but computed from the `SampleStats` of the tile.
+macro_rules! accumulate_sample {
+    (average, $acc:expr, $sample:expr) => {
+        $acc += $sample
+    };
+    (first, $acc:expr, $sample:expr) => {
+        if $acc < 0.0 {
+            $acc = $sample
+        }
+    };
+    ($derived:ident, $acc:expr, $sample:expr) => {
+        let _ = ($acc, $sample);
+    };
+}
+
+macro_rules! resolve_sample {
+    (average, $sum:expr, $stats:expr, $idx:expr) => {
+        $sum / $stats.count($idx) as f32
+    };
+    (first, $value:expr, $stats:expr, $idx:expr) => {
+        $value
+    };
+    (variance, $value:expr, $stats:expr, $idx:expr) => {{
+        let _ = $value;
+        $stats.variance($idx)
+    }};
+    (count, $value:expr, $stats:expr, $idx:expr) => {{
+        let _ = $value;
+        $stats.count($idx) as f32
+    }};
+}
+
 macro_rules! declare_channels {
     {
         $($name:ident => {
             storage: $storage:ident,
             init: $initialize:expr,
+            components: [$($component:expr),+],
+            accumulate: $accumulate:ident,
         }),+
     } => {
         #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -34,11 +77,40 @@ macro_rules! declare_channels {
             $($name,)+
         }
 
+        impl ChannelKind {
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $( ChannelKind::$name => stringify!($name), )+
+                }
+            }
+
+            /// Looks up a channel kind by its name, ignoring case.
+            pub fn from_name(name: &str) -> Option<Self> {
+                $( if name.eq_ignore_ascii_case(stringify!($name)) {
+                    return Some(ChannelKind::$name);
+                } )+
+                None
+            }
+
+            pub fn all() -> &'static [ChannelKind] {
+                &[$( ChannelKind::$name, )+]
+            }
+
+            /// The names of the components of this channel, e.g. `["R", "G", "B"]`.
+            pub fn component_names(&self) -> &'static [&'static str] {
+                match *self {
+                    $( ChannelKind::$name => &[$($component),+], )+
+                }
+            }
+        }
+
         #[derive(Debug)]
         pub enum ChannelSample {
             $($name($storage),)+
         }
 
+        /// The accumulated samples of a region of the film: either one tile while it is being
+        /// rendered, or the whole film across every epoch.
         pub enum ChannelTileStorage {
             $($name(Vec<$storage>),)+
         }
@@ -51,14 +123,56 @@ macro_rules! declare_channels {
                 }
             }
 
+            pub fn kind(&self) -> ChannelKind {
+                match *self {
+                    $( ChannelTileStorage::$name(_) => ChannelKind::$name, )+
+                }
+            }
+
+            fn len(&self) -> usize {
+                match self {
+                    $( ChannelTileStorage::$name(buf) => buf.len(), )+
+                }
+            }
+
+            fn component(&self, idx: usize, component: usize) -> f32 {
+                match self {
+                    $( ChannelTileStorage::$name(buf) => buf[idx].component(component), )+
+                }
+            }
+
+            fn set_component(&mut self, idx: usize, component: usize, value: f32) {
+                match self {
+                    $( ChannelTileStorage::$name(buf) => buf[idx].set_component(component, value), )+
+                }
+            }
+
             fn add_sample(&mut self, idx: usize, sample: &ChannelSample) {
                 match (self, sample) {
                     $((ChannelTileStorage::$name(ref mut buf), ChannelSample::$name(sample)) => {
-                        buf[idx] += *sample;
+                        accumulate_sample!($accumulate, buf[idx], *sample);
                     },)+
                     _ => (),
                 }
             }
+
+            /// Add the samples accumulated in a tile into this storage, which covers the whole film.
+            fn add_tile(&mut self, tile: &ChannelTileStorage, full_res: Extent2u, tile_bounds: Bounds2u) -> Result<(), ()> {
+                let extent = tile_bounds.size();
+                match (self, tile) {
+                    $( (ChannelTileStorage::$name(this_buf), ChannelTileStorage::$name(tile_buf)) => {
+                        for x in 0..extent.w {
+                            for y in 0..extent.h {
+                                let tile_idx = x + y * extent.w;
+                                let this_idx = (tile_bounds.min.x + x) + (tile_bounds.min.y + y) * full_res.w;
+                                accumulate_sample!($accumulate, this_buf[this_idx as usize], tile_buf[tile_idx as usize]);
+                            }
+                        }
+                        Ok(())
+                    }, )+
+                    _ => Err(())
+                }
+            }
         }
 
         pub enum ChannelStorage {
@@ -79,16 +193,22 @@ macro_rules! declare_channels {
                 }
             }
 
-            pub fn copy_from_tile(&mut self, other: &ChannelTileStorage, full_res: Extent2u, tile_bounds: Bounds2u, samples: usize) -> Result<(), ()> {
-                let extent = tile_bounds.size();
-                match (self, other) {
-                    $( (ChannelStorage::$name(this_buf), ChannelTileStorage::$name(tile_buf)) => {
-                        for x in 0..extent.w {
-                            for y in 0..extent.h {
-                                let tile_idx = x + y * extent.w;
-                                let this_idx = (tile_bounds.min.x + x) + (tile_bounds.min.y + y) * full_res.w;
-                                let tile_samp_sum = tile_buf[tile_idx as usize];
-                                this_buf[this_idx as usize] = tile_samp_sum / samples as f32;
+            /// Get one component of the value at `idx` as a linear, unclamped float.
+            pub fn component(&self, idx: usize, component: usize) -> f32 {
+                match self {
+                    $( ChannelStorage::$name(buf) => buf[idx].component(component), )+
+                }
+            }
+
+            /// Resolve the final values of the pixels in `bounds` from the samples accumulated over
+            /// the whole film.
+            pub fn resolve_from(&mut self, sums: &ChannelTileStorage, stats: &SampleStats, full_res: Extent2u, bounds: Bounds2u) -> Result<(), ()> {
+                match (self, sums) {
+                    $( (ChannelStorage::$name(this_buf), ChannelTileStorage::$name(sums_buf)) => {
+                        for x in bounds.min.x..bounds.max.x {
+                            for y in bounds.min.y..bounds.max.y {
+                                let idx = (x + y * full_res.w) as usize;
+                                this_buf[idx] = resolve_sample!($accumulate, sums_buf[idx], stats, idx);
                             }
                         }
                         Ok(())
@@ -100,22 +220,445 @@ macro_rules! declare_channels {
     }
 }
 
+/// A type which can be stored in a channel.
+pub trait ChannelValue: Copy {
+    /// Get one component of the value as a linear float.
+    fn component(&self, component: usize) -> f32;
+
+    /// Set one component of the value from a linear float.
+    fn set_component(&mut self, component: usize, value: f32);
+}
+
+impl ChannelValue for f32 {
+    fn component(&self, _component: usize) -> f32 {
+        *self
+    }
+
+    fn set_component(&mut self, _component: usize, value: f32) {
+        *self = value;
+    }
+}
+
+impl ChannelValue for Vec2 {
+    fn component(&self, component: usize) -> f32 {
+        match component {
+            0 => self.x,
+            _ => self.y,
+        }
+    }
+
+    fn set_component(&mut self, component: usize, value: f32) {
+        match component {
+            0 => self.x = value,
+            _ => self.y = value,
+        }
+    }
+}
+
+impl ChannelValue for Vec3 {
+    fn component(&self, component: usize) -> f32 {
+        match component {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
+
+    fn set_component(&mut self, component: usize, value: f32) {
+        match component {
+            0 => self.x = value,
+            1 => self.y = value,
+            _ => self.z = value,
+        }
+    }
+}
+
+impl ChannelValue for Srgb {
+    fn component(&self, component: usize) -> f32 {
+        self.0.component(component)
+    }
+
+    fn set_component(&mut self, component: usize, value: f32) {
+        self.0.set_component(component, value)
+    }
+}
+
 declare_channels! {
     Color => {
         storage: Srgb,
         init: Srgb::zero(),
+        components: ["R", "G", "B"],
+        accumulate: average,
     },
     Alpha => {
         storage: f32,
         init: 0f32,
+        components: ["A"],
+        accumulate: average,
     },
     Background => {
         storage: Srgb,
         init: Srgb::zero(),
+        components: ["R", "G", "B"],
+        accumulate: average,
     },
     WorldNormal => {
         storage: Vec3,
         init: Vec3::zero(),
+        components: ["X", "Y", "Z"],
+        accumulate: average,
+    },
+    Depth => {
+        storage: f32,
+        init: 0f32,
+        components: ["Z"],
+        accumulate: average,
+    },
+    Albedo => {
+        storage: Srgb,
+        init: Srgb::zero(),
+        components: ["R", "G", "B"],
+        accumulate: average,
+    },
+    MaterialId => {
+        storage: f32,
+        init: -1f32,
+        components: ["ID"],
+        accumulate: first,
+    },
+    WorldPosition => {
+        storage: Vec3,
+        init: Vec3::zero(),
+        components: ["X", "Y", "Z"],
+        accumulate: average,
+    },
+    ObjectId => {
+        storage: f32,
+        init: -1f32,
+        components: ["ID"],
+        accumulate: first,
+    },
+    MotionVector => {
+        storage: Vec2,
+        init: Vec2::zero(),
+        components: ["X", "Y"],
+        accumulate: average,
+    },
+    Variance => {
+        storage: Vec3,
+        init: Vec3::zero(),
+        components: ["R", "G", "B"],
+        accumulate: variance,
+    },
+    SampleCount => {
+        storage: f32,
+        init: 0f32,
+        components: ["N"],
+        accumulate: count,
+    }
+}
+
+/// How many samples (in groups of 4) each pixel gets when rendering a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleBudget {
+    /// Every pixel gets exactly this many samples.
+    Fixed(usize),
+    Adaptive(AdaptiveSampling),
+}
+
+/// Settings for rendering pixels in passes, where after the first pass only pixels whose
+/// relative error is still above `error_threshold` get more samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    /// The samples every pixel gets in the first pass, and has before it can be considered
+    /// converged. A few samples which happen to agree, like ones which all miss a small light,
+    /// have no variance, so this should be more than one.
+    pub min_samples: usize,
+    /// The most samples any pixel can get.
+    pub max_samples: usize,
+    /// The samples added to noisy pixels in each pass after the first.
+    pub pass_samples: usize,
+    /// The relative standard error (see `SampleStats::relative_error`) below which a pixel is
+    /// considered converged.
+    pub error_threshold: f32,
+}
+
+impl From<usize> for SampleBudget {
+    fn from(samples: usize) -> Self {
+        SampleBudget::Fixed(samples)
+    }
+}
+
+impl From<AdaptiveSampling> for SampleBudget {
+    fn from(adaptive: AdaptiveSampling) -> Self {
+        SampleBudget::Adaptive(adaptive)
+    }
+}
+
+impl SampleBudget {
+    pub fn max_samples(&self) -> usize {
+        match *self {
+            SampleBudget::Fixed(samples) => samples,
+            SampleBudget::Adaptive(adaptive) => adaptive.max_samples,
+        }
+    }
+
+    /// Whether the pixel at `tile_coord` is converged, so it gets no more samples: it has at least
+    /// the minimum number of samples, and neither it nor its neighbors are too noisy.
+    fn converged<N: ArrayLength<ChannelTileStorage>>(
+        &self,
+        tile: &Tile<N>,
+        tile_coord: Vec2u,
+    ) -> bool {
+        match *self {
+            SampleBudget::Fixed(_) => false,
+            SampleBudget::Adaptive(adaptive) => {
+                tile.sample_count(tile_coord) >= 4 * adaptive.min_samples as u32
+                    && tile.neighborhood_error(tile_coord) <= adaptive.error_threshold
+            }
+        }
+    }
+
+    /// The range of sample numbers taken in the first pass.
+    fn first_pass(&self) -> Range<usize> {
+        match *self {
+            SampleBudget::Fixed(samples) => 0..samples,
+            SampleBudget::Adaptive(adaptive) => 0..adaptive.min_samples.min(adaptive.max_samples),
+        }
+    }
+
+    /// The range of sample numbers taken in the pass after `pass`, which is empty once the budget
+    /// is used up.
+    fn next_pass(&self, pass: Range<usize>) -> Range<usize> {
+        match *self {
+            SampleBudget::Fixed(_) => pass.end..pass.end,
+            SampleBudget::Adaptive(adaptive) => {
+                pass.end..(pass.end + adaptive.pass_samples.max(1)).min(adaptive.max_samples)
+            }
+        }
+    }
+}
+
+/// Per pixel statistics of the samples taken in a tile or the whole film, which are tracked
+/// regardless of which channels are recorded.
+#[derive(Clone)]
+pub struct SampleStats {
+    /// The number of camera samples taken in each pixel.
+    counts: Vec<u32>,
+    /// The sum and sum of squares of the radiance (Color or Background) of the samples.
+    sums: Vec<Vec3>,
+    sums_sq: Vec<Vec3>,
+}
+
+impl SampleStats {
+    fn new(res: Extent2u) -> Self {
+        let len = (res.w * res.h) as usize;
+        SampleStats {
+            counts: vec![0; len],
+            sums: vec![Vec3::zero(); len],
+            sums_sq: vec![Vec3::zero(); len],
+        }
+    }
+
+    /// Copy the statistics of the pixels in `bounds` out of statistics covering the whole film.
+    fn region(&self, full_res: Extent2u, bounds: Bounds2u) -> Self {
+        let mut region = SampleStats::new(bounds.size());
+        for (tile_idx, film_idx) in region_indices(full_res, bounds) {
+            region.counts[tile_idx] = self.counts[film_idx];
+            region.sums[tile_idx] = self.sums[film_idx];
+            region.sums_sq[tile_idx] = self.sums_sq[film_idx];
+        }
+        region
+    }
+
+    /// Add the statistics of a tile covering `bounds` into these, which cover the whole film.
+    fn add_tile(&mut self, tile: &SampleStats, full_res: Extent2u, bounds: Bounds2u) {
+        for (tile_idx, film_idx) in region_indices(full_res, bounds) {
+            self.counts[film_idx] += tile.counts[tile_idx];
+            self.sums[film_idx] += tile.sums[tile_idx];
+            self.sums_sq[film_idx] += tile.sums_sq[tile_idx];
+        }
+    }
+
+    fn add_camera_samples(&mut self, idx: usize, count: u32) {
+        self.counts[idx] += count;
+    }
+
+    fn add_radiance(&mut self, idx: usize, radiance: Vec3) {
+        self.sums[idx] += radiance;
+        self.sums_sq[idx] += radiance * radiance;
+    }
+
+    pub fn count(&self, idx: usize) -> u32 {
+        self.counts[idx]
+    }
+
+    /// The most camera samples taken in any pixel.
+    pub fn max_count(&self) -> u32 {
+        self.counts.iter().cloned().max().unwrap_or(0)
+    }
+
+    pub fn mean(&self, idx: usize) -> Vec3 {
+        self.sums[idx] / (self.counts[idx] as f32).max(1.0)
+    }
+
+    /// The standard error of the brightest channel of a pixel relative to its mean. Dark pixels
+    /// are given a small floor so that they aren't considered noisy forever.
+    pub fn relative_error(&self, idx: usize) -> f32 {
+        relative_error(self.counts[idx], self.sums[idx], self.sums_sq[idx])
+    }
+
+    /// The variance of the mean of the radiance samples in a pixel, i.e. the squared standard
+    /// error of the pixel's color. Each camera sample which didn't record radiance counts as a
+    /// sample of zero.
+    pub fn variance(&self, idx: usize) -> Vec3 {
+        variance(self.counts[idx], self.sums[idx], self.sums_sq[idx])
+    }
+}
+
+fn variance(count: u32, sum: Vec3, sum_sq: Vec3) -> Vec3 {
+    let n = count as f32;
+    if n < 2.0 {
+        return Vec3::zero();
+    }
+    let sample_variance = (sum_sq - sum * sum / n) / (n - 1.0);
+    (sample_variance / n).map(|x| x.max(0.0))
+}
+
+fn relative_error(count: u32, sum: Vec3, sum_sq: Vec3) -> f32 {
+    const DARK_FLOOR: f32 = 0.01;
+    let mean = sum / (count as f32).max(1.0);
+    let std_error = variance(count, sum, sum_sq).component_max().sqrt();
+    std_error / (mean.component_max() + DARK_FLOOR)
+}
+
+/// Pairs of indices into a tile covering `bounds` and into the whole film for each pixel of the
+/// tile.
+fn region_indices(full_res: Extent2u, bounds: Bounds2u) -> impl Iterator<Item = (usize, usize)> {
+    let extent = bounds.size();
+    (0..extent.h).flat_map(move |y| {
+        (0..extent.w).map(move |x| {
+            let tile_idx = x + y * extent.w;
+            let film_idx = (bounds.min.x + x) + (bounds.min.y + y) * full_res.w;
+            (tile_idx as usize, film_idx as usize)
+        })
+    })
+}
+
+/// The precision to write OpenEXR channels with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+/// A region of the image to render or write, with the origin at the top left corner of the image
+/// like in written images.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CropWindow {
+    /// Pixel coordinates, from `min` inclusive to `max` exclusive.
+    Pixels { min: Vec2u, max: Vec2u },
+    /// Fractions of the image size, where `(0, 0)` is the top left and `(1, 1)` the bottom right
+    /// corner. Pixels partially covered by the window are included.
+    Ndc { min: Vec2, max: Vec2 },
+}
+
+impl CropWindow {
+    /// The pixels covered by the window on a film of resolution `res`, whose rows are counted from
+    /// the bottom. The bounds are clamped to the film and may be empty.
+    pub fn raster_bounds(&self, res: Extent2u) -> Bounds2u {
+        let (min, max) = match *self {
+            CropWindow::Pixels { min, max } => (min, max),
+            CropWindow::Ndc { min, max } => {
+                let to_pixels = |ndc: f32, size: u32, round: fn(f32) -> f32| {
+                    round(ndc.max(0.0).min(1.0) * size as f32) as u32
+                };
+                (
+                    Vec2u::new(
+                        to_pixels(min.x, res.w, f32::floor),
+                        to_pixels(min.y, res.h, f32::floor),
+                    ),
+                    Vec2u::new(
+                        to_pixels(max.x, res.w, f32::ceil),
+                        to_pixels(max.y, res.h, f32::ceil),
+                    ),
+                )
+            }
+        };
+        let flipped = Bounds2u {
+            min: Vec2u::new(min.x, res.h.saturating_sub(max.y)),
+            max: Vec2u::new(max.x, res.h.saturating_sub(min.y)),
+        };
+        flipped.intersect(&Bounds2u {
+            min: Vec2u::new(0, 0),
+            max: Vec2u::new(res.w, res.h),
+        })
+    }
+}
+
+/// The file format to write a channel to with `Film::save_to`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// 8 bit PNG, gamma corrected and clamped to `[0, 1]`.
+    Png,
+    /// Radiance RGBE, linear and unclamped. Negative values can't be represented and are clamped to zero.
+    Hdr,
+    /// Portable float map, linear and unclamped.
+    Pfm,
+}
+
+impl OutputFormat {
+    pub const NAMES: &'static [&'static str] = &["png", "hdr", "pfm"];
+
+    /// Looks up an output format by its name (which is also its file extension), ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Some(OutputFormat::Png),
+            "hdr" => Some(OutputFormat::Hdr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            OutputFormat::Png => "png",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Pfm => "pfm",
+        }
+    }
+}
+
+/// A distinct, arbitrary color used to preview an id channel.
+fn id_color(id: f32) -> image::Rgb<u8> {
+    if id < 0.0 {
+        return image::Rgb([0, 0, 0]);
+    }
+    // Integer hash from https://nullprogram.com/blog/2018/07/31/
+    let mut x = id.round() as u32;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    image::Rgb([x as u8, (x >> 8) as u8, (x >> 16) as u8])
+}
+
+/// The suffix appended to the base name of the files a channel is written to.
+fn file_suffix(kind: ChannelKind) -> &'static str {
+    match kind {
+        ChannelKind::Color => "color",
+        ChannelKind::Alpha => "alpha",
+        ChannelKind::Background => "background",
+        ChannelKind::WorldNormal => "normal",
+        ChannelKind::Depth => "depth",
+        ChannelKind::Albedo => "albedo",
+        ChannelKind::MaterialId => "material",
+        ChannelKind::WorldPosition => "position",
+        ChannelKind::ObjectId => "object",
+        ChannelKind::MotionVector => "motion",
+        ChannelKind::Variance => "variance",
+        ChannelKind::SampleCount => "samples",
     }
 }
 
@@ -133,9 +676,15 @@ pub struct Tile<N: ArrayLength<ChannelTileStorage>> {
     _index: usize,
     epoch: usize,
     channels: GenericArray<ChannelTileStorage, N>,
+    stats: SampleStats,
+    /// The statistics of the samples taken in this tile's pixels in previous epochs.
+    prior_stats: SampleStats,
     raster_bounds: Bounds2u,
     raster_extent: Extent2u,
     screen_to_ndc_size: Vec2,
+    /// Color splatted by integrators anywhere on the film rather than in the tile's own pixels,
+    /// as indices into the whole film.
+    splats: Vec<(usize, Srgb)>,
 }
 
 impl<N: ArrayLength<ChannelTileStorage>> Tile<N> {
@@ -145,6 +694,7 @@ impl<N: ArrayLength<ChannelTileStorage>> Tile<N> {
         channels: IC,
         res: Extent2u,
         raster_bounds: Bounds2u,
+        prior_stats: SampleStats,
     ) -> Self
     where
         IC: std::iter::ExactSizeIterator<Item = ChannelKind>,
@@ -158,29 +708,186 @@ impl<N: ArrayLength<ChannelTileStorage>> Tile<N> {
                 channels.map(|kind| ChannelTileStorage::new(kind, raster_bounds.size())),
             )
             .expect("Incorrect number of channels passed to tile creation"),
+            stats: SampleStats::new(raster_bounds.size()),
+            prior_stats,
             raster_bounds,
             raster_extent: raster_bounds.size(),
             screen_to_ndc_size,
+            splats: Vec::new(),
         }
     }
 
     pub fn add_sample(&mut self, tile_coord: Vec2u, sample: ChannelSample) {
         let idx = tile_coord.x + tile_coord.y * self.raster_extent.w;
+        match sample {
+            ChannelSample::Color(radiance) | ChannelSample::Background(radiance) => {
+                self.stats.add_radiance(idx as usize, radiance.0)
+            }
+            _ => (),
+        }
         for channel in self.channels.iter_mut() {
             channel.add_sample(idx as usize, &sample);
         }
     }
+
+    /// The number of camera samples taken in the pixel at `tile_coord` so far, including previous
+    /// epochs.
+    pub fn sample_count(&self, tile_coord: Vec2u) -> u32 {
+        let idx = (tile_coord.x + tile_coord.y * self.raster_extent.w) as usize;
+        self.prior_stats.counts[idx] + self.stats.counts[idx]
+    }
+
+    /// The number of camera samples taken in the pixel at `tile_coord` in previous epochs.
+    pub fn prior_sample_count(&self, tile_coord: Vec2u) -> u32 {
+        let idx = (tile_coord.x + tile_coord.y * self.raster_extent.w) as usize;
+        self.prior_stats.counts[idx]
+    }
+
+    /// The relative standard error of the pixel at `tile_coord` so far, including previous epochs.
+    pub fn relative_error(&self, tile_coord: Vec2u) -> f32 {
+        let idx = (tile_coord.x + tile_coord.y * self.raster_extent.w) as usize;
+        let (prior, this) = (&self.prior_stats, &self.stats);
+        relative_error(
+            prior.counts[idx] + this.counts[idx],
+            prior.sums[idx] + this.sums[idx],
+            prior.sums_sq[idx] + this.sums_sq[idx],
+        )
+    }
+
+    /// The largest relative standard error of the pixel at `tile_coord` and its neighbors in the
+    /// tile, so a pixel whose samples agree by chance isn't converged while the ones around it
+    /// aren't.
+    pub fn neighborhood_error(&self, tile_coord: Vec2u) -> f32 {
+        let extent = self.raster_extent;
+        let xs = tile_coord.x.saturating_sub(1)..(tile_coord.x + 2).min(extent.w);
+        let ys = tile_coord.y.saturating_sub(1)..(tile_coord.y + 2).min(extent.h);
+        ys.flat_map(|y| xs.clone().map(move |x| Vec2u::new(x, y)))
+            .map(|coord| self.relative_error(coord))
+            .fold(0.0, f32::max)
+    }
+
+    /// Count `count` more camera samples as having been taken in the pixel at `tile_coord`.
+    pub fn add_camera_samples(&mut self, tile_coord: Vec2u, count: u32) {
+        let idx = tile_coord.x + tile_coord.y * self.raster_extent.w;
+        self.stats.add_camera_samples(idx as usize, count);
+    }
 }
 
-pub struct Film<N: ArrayLength<ChannelStorage>> {
+pub struct Film<N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>> {
     channel_indices: HashMap<ChannelKind, usize>,
+    /// The final values of each channel, resolved from `accumulated` whenever a tile finishes.
     channels: Mutex<GenericArray<ChannelStorage, N>>,
+    accumulated: Mutex<Accumulated<N>>,
     progressive_epoch: usize,
     this_epoch_tiles_finished: AtomicUsize,
+    /// The frame being accumulated, which seeds the sample sequences.
+    frame: Option<usize>,
+    /// Whether the channels were changed by `denoise` or `post_process` since they were resolved.
+    post_processed: bool,
+    /// The linear color space the film's colors are in.
+    color_space: ColorSpace,
     res: Extent2u,
 }
 
-impl<'a, N: ArrayLength<ChannelStorage>> Film<N> {
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RAYNCKPT";
+const CHECKPOINT_VERSION: u32 = 3;
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> std::io::Result<f32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn write_budget<W: Write>(writer: &mut W, budget: SampleBudget) -> std::io::Result<()> {
+    match budget {
+        SampleBudget::Fixed(samples) => {
+            writer.write_all(&0u32.to_le_bytes())?;
+            writer.write_all(&(samples as u64).to_le_bytes())
+        }
+        SampleBudget::Adaptive(adaptive) => {
+            writer.write_all(&1u32.to_le_bytes())?;
+            writer.write_all(&(adaptive.min_samples as u64).to_le_bytes())?;
+            writer.write_all(&(adaptive.max_samples as u64).to_le_bytes())?;
+            writer.write_all(&(adaptive.pass_samples as u64).to_le_bytes())?;
+            writer.write_all(&adaptive.error_threshold.to_le_bytes())
+        }
+    }
+}
+
+/// Reads a budget written by `write_budget`, or `None` if it is of an unknown kind.
+fn read_budget<R: Read>(reader: &mut R) -> std::io::Result<Option<SampleBudget>> {
+    Ok(match read_u32(reader)? {
+        0 => Some(SampleBudget::Fixed(read_u64(reader)? as usize)),
+        1 => Some(SampleBudget::Adaptive(AdaptiveSampling {
+            min_samples: read_u64(reader)? as usize,
+            max_samples: read_u64(reader)? as usize,
+            pass_samples: read_u64(reader)? as usize,
+            error_threshold: read_f32(reader)?,
+        })),
+        _ => None,
+    })
+}
+
+/// Everything sampled into a film across all epochs since it was created or last reset.
+struct Accumulated<N: ArrayLength<ChannelTileStorage>> {
+    sums: GenericArray<ChannelTileStorage, N>,
+    stats: SampleStats,
+    /// The sum of the color splatted into each pixel, see `Integrator::integrate`.
+    splats: Vec<Srgb>,
+    /// Whether anything was splatted at all, so films which aren't splatted into skip resolving
+    /// the splats.
+    splatted: bool,
+    /// The total number of camera samples taken in every pixel.
+    camera_samples: u64,
+}
+
+impl<N: ArrayLength<ChannelTileStorage>> Accumulated<N> {
+    fn new(channels: &[ChannelKind], res: Extent2u) -> Self {
+        Accumulated {
+            sums: GenericArray::from_exact_iter(
+                channels
+                    .iter()
+                    .map(|kind| ChannelTileStorage::new(*kind, res)),
+            )
+            .expect("Generic type length does not match the number of channels."),
+            stats: SampleStats::new(res),
+            splats: vec![Srgb::zero(); (res.w * res.h) as usize],
+            splatted: false,
+            camera_samples: 0,
+        }
+    }
+
+    /// Add the splatted color to the pixels in `bounds` of a resolved Color channel. Integrators
+    /// may trace a path from the lights for each camera sample which splats color anywhere on the
+    /// film, so the splats estimate the whole image from that many paths: they're scaled by the
+    /// number of pixels over the number of camera samples.
+    fn resolve_splats(&self, channel: &mut ChannelStorage, full_res: Extent2u, bounds: Bounds2u) {
+        if !self.splatted {
+            return;
+        }
+
+        if let ChannelStorage::Color(buf) = channel {
+            let scale = (full_res.w * full_res.h) as f32 / self.camera_samples.max(1) as f32;
+            for (_, idx) in region_indices(full_res, bounds) {
+                buf[idx] += self.splats[idx] * scale;
+            }
+        }
+    }
+}
+
+impl<'a, N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>> Film<N> {
     pub fn new(channels: &[ChannelKind], res: Extent2u) -> Result<Self, String> {
         let mut channel_indices = HashMap::new();
         for (i, kind) in channels.iter().enumerate() {
@@ -196,18 +903,499 @@ impl<'a, N: ArrayLength<ChannelStorage>> Film<N> {
                 )
                 .expect("Generic type length does not match the number of channels."),
             ),
+            accumulated: Mutex::new(Accumulated::new(channels, res)),
             progressive_epoch: 0,
             this_epoch_tiles_finished: AtomicUsize::new(0),
+            frame: None,
+            post_processed: false,
+            color_space: ColorSpace::LinearSrgb,
             res,
         })
     }
 
-    pub fn save_to<P: AsRef<std::path::Path>, IS: Into<String>>(
+    /// Throw away everything rendered so far, e.g. before rendering the next frame of an
+    /// animation.
+    pub fn reset(&mut self) {
+        let kinds = self.channel_kinds();
+        let res = self.res;
+        *self.accumulated.get_mut().unwrap() = Accumulated::new(&kinds, res);
+        for channel in self.channels.get_mut().unwrap().iter_mut() {
+            *channel = ChannelStorage::new(channel.kind(), res);
+        }
+        self.progressive_epoch = 0;
+        self.frame = None;
+        self.post_processed = false;
+    }
+
+    /// The number of passes rendered into the film since it was created or last reset.
+    pub fn epoch(&self) -> usize {
+        self.progressive_epoch
+    }
+
+    /// The frame the film is accumulating, or `None` if nothing has been rendered into it yet.
+    pub fn frame(&self) -> Option<usize> {
+        self.frame
+    }
+
+    /// The linear color space the rendered colors are in, which should match the working space of
+    /// the scene rendered into the film.
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// Set the color space the rendered colors are in, see `color_space`.
+    pub fn set_color_space(&mut self, space: ColorSpace) {
+        self.color_space = space;
+    }
+
+    /// Write everything accumulated so far (the channel sums, per pixel sample statistics, epoch
+    /// and frame) to a checkpoint file at `path`, from which `load_checkpoint` can continue the
+    /// render. `budget` and `passes` are the samples of the whole frame and the number of passes
+    /// they're split into, which the render can only be continued with. The file is written next
+    /// to `path` first and then moved into place, so a crash while writing leaves any previous
+    /// checkpoint intact.
+    pub fn save_checkpoint<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        budget: SampleBudget,
+        passes: usize,
+    ) -> Result<(), String> {
+        use std::fs::{self, DirBuilder, File};
+        use std::io::BufWriter;
+
+        let path = path.as_ref();
+        let frame = self
+            .frame
+            .ok_or_else(|| String::from("Nothing has been rendered to checkpoint yet"))?;
+
+        if let Some(folder) = path.parent() {
+            DirBuilder::new()
+                .recursive(true)
+                .create(folder)
+                .map_err(|e| format!("Failed to create checkpoint folder: {}", e))?;
+        }
+
+        let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = path.with_file_name(temp_name);
+
+        let accumulated = self.accumulated.lock().unwrap();
+
+        println!("Saving checkpoint to {}...", path.display());
+        let write = || -> std::io::Result<()> {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+
+            writer.write_all(CHECKPOINT_MAGIC)?;
+            writer.write_all(&CHECKPOINT_VERSION.to_le_bytes())?;
+            writer.write_all(&self.res.w.to_le_bytes())?;
+            writer.write_all(&self.res.h.to_le_bytes())?;
+            writer.write_all(&(frame as u64).to_le_bytes())?;
+            writer.write_all(&(self.progressive_epoch as u64).to_le_bytes())?;
+            writer.write_all(&(passes as u64).to_le_bytes())?;
+            write_budget(&mut writer, budget)?;
+
+            writer.write_all(&(accumulated.sums.len() as u32).to_le_bytes())?;
+            for sum in accumulated.sums.iter() {
+                let name = sum.kind().name();
+                writer.write_all(&(name.len() as u32).to_le_bytes())?;
+                writer.write_all(name.as_bytes())?;
+            }
+
+            let stats = &accumulated.stats;
+            for idx in 0..stats.counts.len() {
+                writer.write_all(&stats.counts[idx].to_le_bytes())?;
+                for value in [stats.sums[idx], stats.sums_sq[idx]].iter() {
+                    for component in 0..3 {
+                        writer.write_all(&value.component(component).to_le_bytes())?;
+                    }
+                }
+            }
+
+            for sum in accumulated.sums.iter() {
+                let components = sum.kind().component_names().len();
+                for idx in 0..sum.len() {
+                    for component in 0..components {
+                        writer.write_all(&sum.component(idx, component).to_le_bytes())?;
+                    }
+                }
+            }
+
+            for splat in accumulated.splats.iter() {
+                for component in 0..3 {
+                    writer.write_all(&splat.component(component).to_le_bytes())?;
+                }
+            }
+
+            writer.flush()?;
+            writer.get_ref().sync_all()
+        };
+
+        write()
+            .and_then(|_| fs::rename(&temp_path, path))
+            .map_err(|e| format!("Failed to write checkpoint {}: {}", path.display(), e))
+    }
+
+    /// Replace everything accumulated in the film with the contents of a checkpoint written by
+    /// `save_checkpoint`, so that rendering more passes continues the sample sequences of each
+    /// pixel where they left off. The film must have the same resolution and channels, in the
+    /// same order, as the film the checkpoint was saved from, and the frame must be rendered with
+    /// the same `budget` and number of `passes`.
+    pub fn load_checkpoint<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+        budget: SampleBudget,
+        passes: usize,
+    ) -> Result<(), String> {
+        use std::fs::File;
+        use std::io::BufReader;
+
+        let path = path.as_ref();
+        let io_error =
+            |e: std::io::Error| format!("Failed to read checkpoint {}: {}", path.display(), e);
+
+        let mut reader = BufReader::new(File::open(path).map_err(io_error)?);
+
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic).map_err(io_error)?;
+        if &magic != CHECKPOINT_MAGIC
+            || read_u32(&mut reader).map_err(io_error)? != CHECKPOINT_VERSION
+        {
+            return Err(format!(
+                "{} is not a checkpoint from this version of rayn",
+                path.display()
+            ));
+        }
+
+        let res = Extent2u::new(
+            read_u32(&mut reader).map_err(io_error)?,
+            read_u32(&mut reader).map_err(io_error)?,
+        );
+        if res.w != self.res.w || res.h != self.res.h {
+            return Err(format!(
+                "Checkpoint {} is {}x{}, but the film is {}x{}",
+                path.display(),
+                res.w,
+                res.h,
+                self.res.w,
+                self.res.h
+            ));
+        }
+
+        let frame = read_u64(&mut reader).map_err(io_error)? as usize;
+        let epoch = read_u64(&mut reader).map_err(io_error)? as usize;
+
+        let checkpoint_passes = read_u64(&mut reader).map_err(io_error)? as usize;
+        let checkpoint_budget = read_budget(&mut reader).map_err(io_error)?.ok_or_else(|| {
+            format!(
+                "{} is not a checkpoint from this version of rayn",
+                path.display()
+            )
+        })?;
+        if checkpoint_passes != passes || checkpoint_budget != budget {
+            return Err(format!(
+                "Checkpoint {} was rendered with {:?} in {} passes, but this render uses {:?} in {} passes",
+                path.display(),
+                checkpoint_budget,
+                checkpoint_passes,
+                budget,
+                passes
+            ));
+        }
+
+        let num_channels = read_u32(&mut reader).map_err(io_error)?;
+        let mut names = Vec::new();
+        for _ in 0..num_channels {
+            let mut name = vec![0u8; read_u32(&mut reader).map_err(io_error)? as usize];
+            reader.read_exact(&mut name).map_err(io_error)?;
+            names.push(String::from_utf8_lossy(&name).into_owned());
+        }
+        let kinds = self.channel_kinds();
+        if names.len() != kinds.len()
+            || names
+                .iter()
+                .zip(kinds.iter())
+                .any(|(name, kind)| name != kind.name())
+        {
+            return Err(format!(
+                "Checkpoint {} records the channels {:?}, but the film records {:?}",
+                path.display(),
+                names,
+                kinds
+            ));
+        }
+
+        let mut accumulated = Accumulated::<N>::new(&kinds, res);
+
+        let stats = &mut accumulated.stats;
+        for idx in 0..stats.counts.len() {
+            stats.counts[idx] = read_u32(&mut reader).map_err(io_error)?;
+            for value in [&mut stats.sums[idx], &mut stats.sums_sq[idx]].iter_mut() {
+                for component in 0..3 {
+                    value.set_component(component, read_f32(&mut reader).map_err(io_error)?);
+                }
+            }
+        }
+
+        for sum in accumulated.sums.iter_mut() {
+            let components = sum.kind().component_names().len();
+            for idx in 0..sum.len() {
+                for component in 0..components {
+                    sum.set_component(idx, component, read_f32(&mut reader).map_err(io_error)?);
+                }
+            }
+        }
+
+        for splat in accumulated.splats.iter_mut() {
+            for component in 0..3 {
+                splat.set_component(component, read_f32(&mut reader).map_err(io_error)?);
+            }
+            accumulated.splatted |= splat.max_channel() != 0.0;
+        }
+        accumulated.camera_samples = accumulated.stats.counts.iter().map(|&c| c as u64).sum();
+
+        *self.accumulated.get_mut().unwrap() = accumulated;
+        self.progressive_epoch = epoch;
+        self.frame = Some(frame);
+        self.resolve_all();
+
+        Ok(())
+    }
+
+    /// Resolve every pixel of every channel from the accumulated samples, which undoes `denoise`
+    /// and `post_process`.
+    fn resolve_all(&mut self) {
+        let res = self.res;
+        let bounds = Bounds2u {
+            min: Vec2u::new(0, 0),
+            max: Vec2u::new(res.w, res.h),
+        };
+        let accumulated = self.accumulated.get_mut().unwrap();
+        for (sum, channel) in accumulated
+            .sums
+            .iter()
+            .zip(self.channels.get_mut().unwrap().iter_mut())
+        {
+            channel
+                .resolve_from(sum, &accumulated.stats, res, bounds)
+                .unwrap();
+            accumulated.resolve_splats(channel, res, bounds);
+        }
+        self.post_processed = false;
+    }
+
+    /// Denoise the Color channel in place, guided by the WorldNormal, Albedo and Depth channels if
+    /// the film records them and by the variance of each pixel's samples. Call this after
+    /// rendering and before `save_to`; the film goes back to the noisy values when the next pass
+    /// is rendered into it.
+    pub fn denoise(&mut self, settings: &DenoiseSettings) -> Result<(), String> {
+        let color_idx = *self
+            .channel_indices
+            .get(&ChannelKind::Color)
+            .ok_or_else(|| {
+                String::from("Attempted to denoise Color channel but it didn't exist")
+            })?;
+
+        // Only the samples taken in each pixel are measured, so the filter would have no idea how
+        // noisy splatted color is.
+        let accumulated = self.accumulated.get_mut().unwrap();
+        if accumulated.splatted {
+            return Err(String::from(
+                "Attempted to denoise Color channel but color was splatted into it",
+            ));
+        }
+        let variance = (0..(self.res.w * self.res.h) as usize)
+            .map(|idx| accumulated.stats.variance(idx))
+            .collect::<Vec<_>>();
+
+        let channels = self.channels.get_mut().unwrap();
+
+        // Take the color out of the film so the guides can be borrowed alongside it.
+        let mut color = match &mut channels[color_idx] {
+            ChannelStorage::Color(buf) => std::mem::take(buf),
+            _ => panic!("Attempted to index into channel storage array with wrong channel type."),
+        };
+
+        {
+            let (channel_indices, storage) = (&self.channel_indices, &*channels);
+            let guide =
+                move |kind: ChannelKind| channel_indices.get(&kind).map(|&idx| &storage[idx]);
+            let guides = Guides {
+                normal: guide(ChannelKind::WorldNormal).map(|storage| match storage {
+                    ChannelStorage::WorldNormal(buf) => &buf[..],
+                    _ => unreachable!(),
+                }),
+                albedo: guide(ChannelKind::Albedo).map(|storage| match storage {
+                    ChannelStorage::Albedo(buf) => &buf[..],
+                    _ => unreachable!(),
+                }),
+                depth: guide(ChannelKind::Depth).map(|storage| match storage {
+                    ChannelStorage::Depth(buf) => &buf[..],
+                    _ => unreachable!(),
+                }),
+            };
+
+            denoise::denoise(&mut color, &variance, &guides, self.res, settings);
+        }
+
+        channels[color_idx] = ChannelStorage::Color(color);
+        self.post_processed = true;
+        Ok(())
+    }
+
+    /// Apply the given effects, in order, to the linear HDR Color channel. Like `denoise`, call this
+    /// after rendering and before `save_to`.
+    pub fn post_process(&mut self, effects: &[Box<dyn PostEffect>]) -> Result<(), String> {
+        let color_idx = *self
+            .channel_indices
+            .get(&ChannelKind::Color)
+            .ok_or_else(|| {
+                String::from("Attempted to post process Color channel but it didn't exist")
+            })?;
+        let channels = self.channels.get_mut().unwrap();
+        let color = match &mut channels[color_idx] {
+            ChannelStorage::Color(buf) => buf,
+            _ => panic!("Attempted to index into channel storage array with wrong channel type."),
+        };
+        for effect in effects.iter() {
+            effect.apply(color, self.res);
+        }
+        self.post_processed = true;
+        Ok(())
+    }
+
+    /// The kinds of all the channels this film records, in the order they were given.
+    pub fn channel_kinds(&self) -> Vec<ChannelKind> {
+        self.channels
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| c.kind())
+            .collect()
+    }
+
+    /// The pixels to write for the given crop window, or the whole film if there is none.
+    fn output_bounds(&self, crop: Option<CropWindow>) -> Result<Bounds2u, String> {
+        let full = Bounds2u {
+            min: Vec2u::new(0, 0),
+            max: Vec2u::new(self.res.w, self.res.h),
+        };
+        match crop.map(|crop| crop.raster_bounds(self.res)) {
+            Some(bounds) if bounds.is_empty() => {
+                Err(String::from("The crop window doesn't contain any pixels"))
+            }
+            Some(bounds) => Ok(bounds),
+            None => Ok(full),
+        }
+    }
+
+    /// Write the given channels into a single multi-layer OpenEXR file named `{base_name}.exr`,
+    /// with one layer per channel named after it. Unlike `save_to`, values are written linearly and
+    /// unclamped, and the Color layer does not include the Background. If `crop` is given, only the
+    /// pixels inside it are written, with the data window set to the crop and the display window
+    /// to the full film.
+    pub fn save_exr<P: AsRef<std::path::Path>, IS: Into<String>>(
         &self,
         write_channels: &[ChannelKind],
         output_folder: P,
         base_name: IS,
+        precision: ExrPrecision,
+        crop: Option<CropWindow>,
+    ) -> Result<(), String> {
+        use exr::prelude::*;
+        use std::fs::DirBuilder;
+
+        DirBuilder::new()
+            .recursive(true)
+            .create(output_folder.as_ref())
+            .map_err(|e| format!("Failed to create output folder: {}", e))?;
+
+        let channels = self.channels.lock().unwrap();
+
+        let region = self.output_bounds(crop)?;
+        let (w, h) = (region.size().w as usize, region.size().h as usize);
+        let (min_x, max_y, res_w) = (
+            region.min.x as usize,
+            region.max.y as usize,
+            self.res.w as usize,
+        );
+
+        let mut layers = Vec::with_capacity(write_channels.len());
+        for kind in write_channels.iter() {
+            let idx = *self.channel_indices.get(kind).ok_or_else(|| {
+                format!("Attempted to write {:?} channel but it didn't exist", kind)
+            })?;
+            let storage = &channels[idx];
+
+            let exr_channels = kind
+                .component_names()
+                .iter()
+                .enumerate()
+                .map(|(component, name)| {
+                    // OpenEXR stores rows top to bottom, while the film stores them bottom to top.
+                    let values = (0..h)
+                        .flat_map(|y| (0..w).map(move |x| (min_x + x) + (max_y - 1 - y) * res_w))
+                        .map(|i| storage.component(i, component));
+
+                    let samples = match precision {
+                        ExrPrecision::Half => FlatSamples::F16(values.map(f16::from_f32).collect()),
+                        ExrPrecision::Float => FlatSamples::F32(values.collect()),
+                    };
+
+                    AnyChannel::new(*name, samples)
+                })
+                .collect::<Vec<_>>();
+
+            // The data window is positioned relative to the top left of the display window.
+            let mut attributes = LayerAttributes::named(kind.name());
+            attributes.layer_position =
+                exr::math::Vec2(region.min.x as i32, (self.res.h - region.max.y) as i32);
+
+            layers.push(Layer::new(
+                (w, h),
+                attributes,
+                Encoding::SMALL_LOSSLESS,
+                AnyChannels::sort(SmallVec::from_vec(exr_channels)),
+            ));
+        }
+
+        let mut image_attributes = ImageAttributes::new(IntegerBounds::from_dimensions((
+            self.res.w as usize,
+            self.res.h as usize,
+        )));
+        // Tag the image with the primaries and white point of the color space it's in.
+        let [red, green, blue, white] = self.color_space.chromaticities();
+        image_attributes.chromaticities = Some(exr::meta::attribute::Chromaticities {
+            red: exr::math::Vec2(red.0, red.1),
+            green: exr::math::Vec2(green.0, green.1),
+            blue: exr::math::Vec2(blue.0, blue.1),
+            white: exr::math::Vec2(white.0, white.1),
+        });
+
+        let image = Image::from_layers(image_attributes, layers);
+
+        let filename = output_folder
+            .as_ref()
+            .join(format!("{}.exr", base_name.into()));
+        println!("Saving to {}...", filename.display());
+        image
+            .write()
+            .to_file(&filename)
+            .map_err(|e| format!("Failed to write {}: {}", filename.display(), e))
+    }
+
+    /// Write each of the given channels into its own file named `{base_name}_{channel}.{ext}`
+    /// in the chosen format. When `transparent_background` is false, the Color channel is
+    /// composited over the Background channel if it exists. PNGs of the Color and Background
+    /// channels are mapped to display values with `display`, and those of the Albedo channel are
+    /// encoded for its output, while float formats are left linear. If `crop` is given, only the pixels inside it are written.
+    pub fn save_to<P: AsRef<std::path::Path>, IS: Into<String>>(
+        &self,
+        write_channels: &[(ChannelKind, OutputFormat)],
+        output_folder: P,
+        base_name: IS,
         transparent_background: bool,
+        display: DisplayTransform,
+        crop: Option<CropWindow>,
     ) -> Result<(), String> {
         use std::fs::DirBuilder;
         DirBuilder::new()
@@ -217,10 +1405,33 @@ impl<'a, N: ArrayLength<ChannelStorage>> Film<N> {
 
         let base_name = base_name.into();
 
+        let region = self.output_bounds(crop)?;
+        let out = region.size();
+        // Images store rows top to bottom, while the film stores them bottom to top.
+        let film_index = |x: u32, y: u32| (region.min.x + x) + (region.max.y - 1 - y) * self.res.w;
+
         let channels = self.channels.lock().unwrap();
 
-        for kind in write_channels.iter() {
-            match *kind {
+        for &(kind, format) in write_channels.iter() {
+            if format != OutputFormat::Png {
+                let filename = output_folder.as_ref().join(format!(
+                    "{}_{}.{}",
+                    base_name,
+                    file_suffix(kind),
+                    format.extension()
+                ));
+                self.save_float(
+                    &channels,
+                    kind,
+                    format,
+                    &filename,
+                    transparent_background,
+                    region,
+                )?;
+                continue;
+            }
+
+            match kind {
                 ChannelKind::Color => {
                     let color_idx = self.channel_indices.get(&ChannelKind::Color);
                     let alpha_idx = self.channel_indices.get(&ChannelKind::Alpha);
@@ -230,66 +1441,58 @@ impl<'a, N: ArrayLength<ChannelStorage>> Film<N> {
                         (Some(&color_idx), Some(&alpha_idx), _, true) => {
                             let color_buf = &channel_storage_index!(channels, Color, color_idx);
                             let alpha_buf = &channel_storage_index!(channels, Alpha, alpha_idx);
-                            let mut img =
-                                image::RgbaImage::new(self.res.w, self.res.h);
-                            for (x, y, pixel) in img.enumerate_pixels_mut() {
-                                let idx = x + (self.res.h - 1 - y) * self.res.w;
-                                let col = color_buf[idx as usize];
-                                let a = alpha_buf[idx as usize];
-                                let rgb = (col).saturated().gamma_corrected(2.2);
-                                *pixel = image::Rgba([
-                                    (rgb.x * 255.0).min(255.0).max(0.0) as u8,
-                                    (rgb.y * 255.0).min(255.0).max(0.0) as u8,
-                                    (rgb.z * 255.0).min(255.0).max(0.0) as u8,
-                                    (a * 255.0).min(255.0).max(0.0) as u8,
-                                ]);
+                            let mut values = Vec::with_capacity((out.w * out.h * 4) as usize);
+                            for y in 0..out.h {
+                                for x in 0..out.w {
+                                    let idx = film_index(x, y) as usize;
+                                    let rgb = display.apply(color_buf[idx], self.color_space);
+                                    values.extend_from_slice(&[
+                                        rgb.x,
+                                        rgb.y,
+                                        rgb.z,
+                                        alpha_buf[idx],
+                                    ]);
+                                }
                             }
                             let filename = output_folder
                                 .as_ref()
                                 .join(format!("{}_color.png", base_name.clone()));
                             println!("Saving to {}...", filename.display());
-                            img.save(filename).unwrap();
+                            save_display_png(&filename, out, 4, &values, display.output)?;
                         }
                         (Some(&color_idx), _, Some(&bg_idx), false) => {
                             let color_buf = channel_storage_index!(channels, Color, color_idx);
                             let bg_buf = channel_storage_index!(channels, Background, bg_idx);
-                            let mut img =
-                                image::RgbImage::new(self.res.w, self.res.h);
-                            for (x, y, pixel) in img.enumerate_pixels_mut() {
-                                let i = x + (self.res.h - 1 - y) * self.res.w;
-                                let col = color_buf[i as usize];
-                                let bg = bg_buf[i as usize];
-                                let rgb = (col + bg).saturated().gamma_corrected(2.2);
-                                *pixel = image::Rgb([
-                                    (rgb.x * 255.0).min(255.0).max(0.0) as u8,
-                                    (rgb.y * 255.0).min(255.0).max(0.0) as u8,
-                                    (rgb.z * 255.0).min(255.0).max(0.0) as u8,
-                                ]);
+                            let mut values = Vec::with_capacity((out.w * out.h * 3) as usize);
+                            for y in 0..out.h {
+                                for x in 0..out.w {
+                                    let i = film_index(x, y) as usize;
+                                    let rgb =
+                                        display.apply(color_buf[i] + bg_buf[i], self.color_space);
+                                    values.extend_from_slice(&[rgb.x, rgb.y, rgb.z]);
+                                }
                             }
                             let filename = output_folder
                                 .as_ref()
                                 .join(format!("{}_color.png", base_name.clone()));
                             println!("Saving to {}...", filename.display());
-                            img.save(filename).unwrap();
+                            save_display_png(&filename, out, 3, &values, display.output)?;
                         }
                         (Some(&color_idx), _, None, false) => {
                             let color_buf = channel_storage_index!(channels, Color, color_idx);
-                            let mut img =
-                                image::RgbImage::new(self.res.w, self.res.h);
-                            for (x, y, pixel) in img.enumerate_pixels_mut() {
-                                let idx = x + (self.res.h - 1 - y) * self.res.w;
-                                let rgb = color_buf[idx as usize].gamma_corrected(2.2);
-                                *pixel = image::Rgb([
-                                    (rgb.x * 255.0).min(255.0).max(0.0) as u8,
-                                    (rgb.y * 255.0).min(255.0).max(0.0) as u8,
-                                    (rgb.z * 255.0).min(255.0).max(0.0) as u8,
-                                ]);
+                            let mut values = Vec::with_capacity((out.w * out.h * 3) as usize);
+                            for y in 0..out.h {
+                                for x in 0..out.w {
+                                    let idx = film_index(x, y) as usize;
+                                    let rgb = display.apply(color_buf[idx], self.color_space);
+                                    values.extend_from_slice(&[rgb.x, rgb.y, rgb.z]);
+                                }
                             }
                             let filename = output_folder
                                 .as_ref()
                                 .join(format!("{}_color.png", base_name.clone()));
                             println!("Saving to {}...", filename.display());
-                            img.save(filename).unwrap();
+                            save_display_png(&filename, out, 3, &values, display.output)?;
                         }
                         _ => {
                             return Err(String::from(
@@ -308,21 +1511,19 @@ impl<'a, N: ArrayLength<ChannelStorage>> Film<N> {
                             )
                         })?;
                     let buf = channel_storage_index!(channels, Background, idx);
-                    let mut img = image::RgbImage::new(self.res.w, self.res.h);
-                    for (x, y, pixel) in img.enumerate_pixels_mut() {
-                        let idx = x + (self.res.h - 1 - y) * self.res.w;
-                        let rgb = buf[idx as usize].saturated().gamma_corrected(2.2);
-                        *pixel = image::Rgb([
-                            (rgb.x * 255.0).min(255.0).max(0.0) as u8,
-                            (rgb.y * 255.0).min(255.0).max(0.0) as u8,
-                            (rgb.z * 255.0).min(255.0).max(0.0) as u8,
-                        ]);
+                    let mut values = Vec::with_capacity((out.w * out.h * 3) as usize);
+                    for y in 0..out.h {
+                        for x in 0..out.w {
+                            let rgb =
+                                display.apply(buf[film_index(x, y) as usize], self.color_space);
+                            values.extend_from_slice(&[rgb.x, rgb.y, rgb.z]);
+                        }
                     }
                     let filename = output_folder
                         .as_ref()
                         .join(format!("{}_background.png", base_name.clone()));
                     println!("Saving to {}...", filename.display());
-                    img.save(filename).unwrap();
+                    save_display_png(&filename, out, 3, &values, display.output)?;
                 }
                 ChannelKind::WorldNormal => {
                     let idx = *self
@@ -334,9 +1535,9 @@ impl<'a, N: ArrayLength<ChannelStorage>> Film<N> {
                             )
                         })?;
                     let buf = channel_storage_index!(channels, WorldNormal, idx);
-                    let mut img = image::RgbImage::new(self.res.w, self.res.h);
+                    let mut img = image::RgbImage::new(out.w, out.h);
                     for (x, y, pixel) in img.enumerate_pixels_mut() {
-                        let idx = x + (self.res.h - 1 - y) * self.res.w;
+                        let idx = film_index(x, y);
                         let vec = buf[idx as usize];
                         let rgb = Srgb::from(vec * 0.5 + Vec3::new(0.5, 0.5, 0.5));
                         *pixel = image::Rgb([
@@ -359,9 +1560,9 @@ impl<'a, N: ArrayLength<ChannelStorage>> Film<N> {
                             String::from("Attempted to write Alpha channel but it didn't exist")
                         })?;
                     let buf = channel_storage_index!(channels, Alpha, idx);
-                    let mut img = image::GrayImage::new(self.res.w, self.res.h);
+                    let mut img = image::GrayImage::new(out.w, out.h);
                     for (x, y, pixel) in img.enumerate_pixels_mut() {
-                        let idx = x + (self.res.h - 1 - y) * self.res.w;
+                        let idx = film_index(x, y);
                         let a = buf[idx as usize];
                         *pixel = image::Luma([(a * 255.0).min(255.0).max(0.0) as u8]);
                     }
@@ -371,15 +1572,349 @@ impl<'a, N: ArrayLength<ChannelStorage>> Film<N> {
                     println!("Saving to {}...", filename.display());
                     img.save(filename).unwrap();
                 }
+                ChannelKind::Depth => {
+                    let idx = *self
+                        .channel_indices
+                        .get(&ChannelKind::Depth)
+                        .ok_or_else(|| {
+                            String::from("Attempted to write Depth channel but it didn't exist")
+                        })?;
+                    let buf = channel_storage_index!(channels, Depth, idx);
+                    // Depth is unbounded, so for preview it is normalized so that the farthest
+                    // surface in the frame is white. Pixels the film saw the background through
+                    // would make everything else black, so only ones covered by surfaces count.
+                    let alpha_buf = self
+                        .channel_indices
+                        .get(&ChannelKind::Alpha)
+                        .map(|&alpha_idx| channel_storage_index!(channels, Alpha, alpha_idx));
+                    let max_depth = (0..buf.len())
+                        .filter(|&i| alpha_buf.map_or(true, |alpha| alpha[i] > 0.0))
+                        .map(|i| buf[i])
+                        .fold(0.0, f32::max);
+                    let scale = if max_depth > 0.0 {
+                        1.0 / max_depth
+                    } else {
+                        0.0
+                    };
+                    let mut img = image::GrayImage::new(out.w, out.h);
+                    for (x, y, pixel) in img.enumerate_pixels_mut() {
+                        let idx = film_index(x, y);
+                        let d = buf[idx as usize] * scale;
+                        *pixel = image::Luma([(d * 255.0).min(255.0).max(0.0) as u8]);
+                    }
+                    let filename = output_folder
+                        .as_ref()
+                        .join(format!("{}_depth.png", base_name.clone()));
+                    println!("Saving to {}...", filename.display());
+                    img.save(filename).unwrap();
+                }
+                ChannelKind::Albedo => {
+                    let idx = *self
+                        .channel_indices
+                        .get(&ChannelKind::Albedo)
+                        .ok_or_else(|| {
+                            String::from("Attempted to write Albedo channel but it didn't exist")
+                        })?;
+                    let buf = channel_storage_index!(channels, Albedo, idx);
+                    // Albedos are reflectances in [0, 1], so unlike Color they're written without
+                    // exposure or tone mapping, only converted to the output's color space.
+                    let output = display.output;
+                    let mut values = Vec::with_capacity((out.w * out.h * 3) as usize);
+                    for y in 0..out.h {
+                        for x in 0..out.w {
+                            let albedo = self
+                                .color_space
+                                .convert(buf[film_index(x, y) as usize].0, output.space());
+                            let rgb = output.encode(albedo.map(|x| x.max(0.0).min(1.0)));
+                            values.extend_from_slice(&[rgb.x, rgb.y, rgb.z]);
+                        }
+                    }
+                    let filename = output_folder
+                        .as_ref()
+                        .join(format!("{}_albedo.png", base_name.clone()));
+                    println!("Saving to {}...", filename.display());
+                    save_display_png(&filename, out, 3, &values, output)?;
+                }
+                ChannelKind::MaterialId => {
+                    let idx = *self
+                        .channel_indices
+                        .get(&ChannelKind::MaterialId)
+                        .ok_or_else(|| {
+                            String::from(
+                                "Attempted to write MaterialId channel but it didn't exist",
+                            )
+                        })?;
+                    let buf = channel_storage_index!(channels, MaterialId, idx);
+                    let mut img = image::RgbImage::new(out.w, out.h);
+                    for (x, y, pixel) in img.enumerate_pixels_mut() {
+                        let idx = film_index(x, y);
+                        *pixel = id_color(buf[idx as usize]);
+                    }
+                    let filename = output_folder
+                        .as_ref()
+                        .join(format!("{}_material.png", base_name.clone()));
+                    println!("Saving to {}...", filename.display());
+                    img.save(filename).unwrap();
+                }
+                ChannelKind::WorldPosition => {
+                    let idx = *self
+                        .channel_indices
+                        .get(&ChannelKind::WorldPosition)
+                        .ok_or_else(|| {
+                            String::from(
+                                "Attempted to write WorldPosition channel but it didn't exist",
+                            )
+                        })?;
+                    let buf = channel_storage_index!(channels, WorldPosition, idx);
+                    // Positions are unbounded, so for preview they are scaled to fit the
+                    // farthest position in the frame and then mapped like normals. As with Depth,
+                    // only pixels covered by surfaces count, or the background's distant hits
+                    // would squash everything else towards grey.
+                    let alpha_buf = self
+                        .channel_indices
+                        .get(&ChannelKind::Alpha)
+                        .map(|&alpha_idx| channel_storage_index!(channels, Alpha, alpha_idx));
+                    let max_extent = (0..buf.len())
+                        .filter(|&i| alpha_buf.map_or(true, |alpha| alpha[i] > 0.0))
+                        .map(|i| buf[i])
+                        .map(|p| p.x.abs().max(p.y.abs()).max(p.z.abs()))
+                        .fold(0.0, f32::max);
+                    let scale = if max_extent > 0.0 {
+                        1.0 / max_extent
+                    } else {
+                        0.0
+                    };
+                    let mut img = image::RgbImage::new(out.w, out.h);
+                    for (x, y, pixel) in img.enumerate_pixels_mut() {
+                        let idx = film_index(x, y);
+                        let vec = buf[idx as usize] * scale;
+                        let rgb = Srgb::from(vec * 0.5 + Vec3::new(0.5, 0.5, 0.5));
+                        *pixel = image::Rgb([
+                            (rgb.x * 255.0).min(255.0).max(0.0) as u8,
+                            (rgb.y * 255.0).min(255.0).max(0.0) as u8,
+                            (rgb.z * 255.0).min(255.0).max(0.0) as u8,
+                        ]);
+                    }
+                    let filename = output_folder
+                        .as_ref()
+                        .join(format!("{}_position.png", base_name.clone()));
+                    println!("Saving to {}...", filename.display());
+                    img.save(filename).unwrap();
+                }
+                ChannelKind::ObjectId => {
+                    let idx = *self
+                        .channel_indices
+                        .get(&ChannelKind::ObjectId)
+                        .ok_or_else(|| {
+                            String::from("Attempted to write ObjectId channel but it didn't exist")
+                        })?;
+                    let buf = channel_storage_index!(channels, ObjectId, idx);
+                    let mut img = image::RgbImage::new(out.w, out.h);
+                    for (x, y, pixel) in img.enumerate_pixels_mut() {
+                        let idx = film_index(x, y);
+                        *pixel = id_color(buf[idx as usize]);
+                    }
+                    let filename = output_folder
+                        .as_ref()
+                        .join(format!("{}_object.png", base_name.clone()));
+                    println!("Saving to {}...", filename.display());
+                    img.save(filename).unwrap();
+                }
+                ChannelKind::MotionVector => {
+                    let idx = *self
+                        .channel_indices
+                        .get(&ChannelKind::MotionVector)
+                        .ok_or_else(|| {
+                            String::from(
+                                "Attempted to write MotionVector channel but it didn't exist",
+                            )
+                        })?;
+                    let buf = channel_storage_index!(channels, MotionVector, idx);
+                    // For preview, motion is scaled so the fastest pixel in the frame saturates.
+                    let max_motion = buf.iter().map(|v| v.mag()).fold(0.0, f32::max);
+                    let scale = if max_motion > 0.0 {
+                        1.0 / max_motion
+                    } else {
+                        0.0
+                    };
+                    let mut img = image::RgbImage::new(out.w, out.h);
+                    for (x, y, pixel) in img.enumerate_pixels_mut() {
+                        let idx = film_index(x, y);
+                        let v = buf[idx as usize] * scale * 0.5 + Vec2::new(0.5, 0.5);
+                        *pixel = image::Rgb([
+                            (v.x * 255.0).min(255.0).max(0.0) as u8,
+                            (v.y * 255.0).min(255.0).max(0.0) as u8,
+                            127,
+                        ]);
+                    }
+                    let filename = output_folder
+                        .as_ref()
+                        .join(format!("{}_motion.png", base_name.clone()));
+                    println!("Saving to {}...", filename.display());
+                    img.save(filename).unwrap();
+                }
+                ChannelKind::Variance => {
+                    let idx = *self
+                        .channel_indices
+                        .get(&ChannelKind::Variance)
+                        .ok_or_else(|| {
+                            String::from("Attempted to write Variance channel but it didn't exist")
+                        })?;
+                    let buf = channel_storage_index!(channels, Variance, idx);
+                    // For preview, show the standard error scaled so the noisiest pixel is white.
+                    let max_error = buf
+                        .iter()
+                        .map(|v| v.component_max().sqrt())
+                        .fold(0.0, f32::max);
+                    let scale = if max_error > 0.0 {
+                        1.0 / max_error
+                    } else {
+                        0.0
+                    };
+                    let mut img = image::RgbImage::new(out.w, out.h);
+                    for (x, y, pixel) in img.enumerate_pixels_mut() {
+                        let idx = film_index(x, y);
+                        let rgb = buf[idx as usize].map(|v| v.sqrt() * scale);
+                        *pixel = image::Rgb([
+                            (rgb.x * 255.0).min(255.0).max(0.0) as u8,
+                            (rgb.y * 255.0).min(255.0).max(0.0) as u8,
+                            (rgb.z * 255.0).min(255.0).max(0.0) as u8,
+                        ]);
+                    }
+                    let filename = output_folder
+                        .as_ref()
+                        .join(format!("{}_variance.png", base_name.clone()));
+                    println!("Saving to {}...", filename.display());
+                    img.save(filename).unwrap();
+                }
+                ChannelKind::SampleCount => {
+                    let idx = *self
+                        .channel_indices
+                        .get(&ChannelKind::SampleCount)
+                        .ok_or_else(|| {
+                            String::from(
+                                "Attempted to write SampleCount channel but it didn't exist",
+                            )
+                        })?;
+                    let buf = channel_storage_index!(channels, SampleCount, idx);
+                    // For preview, scale so the pixel with the most samples is white.
+                    let max_count = buf.iter().cloned().fold(0.0, f32::max);
+                    let scale = if max_count > 0.0 {
+                        1.0 / max_count
+                    } else {
+                        0.0
+                    };
+                    let mut img = image::GrayImage::new(out.w, out.h);
+                    for (x, y, pixel) in img.enumerate_pixels_mut() {
+                        let idx = film_index(x, y);
+                        let n = buf[idx as usize] * scale;
+                        *pixel = image::Luma([(n * 255.0).min(255.0).max(0.0) as u8]);
+                    }
+                    let filename = output_folder
+                        .as_ref()
+                        .join(format!("{}_samples.png", base_name.clone()));
+                    println!("Saving to {}...", filename.display());
+                    img.save(filename).unwrap();
+                }
             }
         }
         Ok(())
     }
+
+    /// Write a channel linearly and unclamped to a Radiance HDR or PFM file. Channels with a single
+    /// component are written as grey.
+    fn save_float(
+        &self,
+        channels: &[ChannelStorage],
+        kind: ChannelKind,
+        format: OutputFormat,
+        filename: &std::path::Path,
+        transparent_background: bool,
+        region: Bounds2u,
+    ) -> Result<(), String> {
+        use std::fs::File;
+        use std::io::BufWriter;
+
+        let idx = *self
+            .channel_indices
+            .get(&kind)
+            .ok_or_else(|| format!("Attempted to write {:?} channel but it didn't exist", kind))?;
+        let storage = &channels[idx];
+        let background = match (kind, transparent_background) {
+            (ChannelKind::Color, false) => self
+                .channel_indices
+                .get(&ChannelKind::Background)
+                .map(|&bg_idx| &channels[bg_idx]),
+            _ => None,
+        };
+        let components = kind.component_names().len();
+
+        let pixel = |idx: usize| -> [f32; 3] {
+            let mut rgb = [0.0; 3];
+            for (c, value) in rgb.iter_mut().enumerate() {
+                let component = c.min(components - 1);
+                *value = storage.component(idx, component)
+                    + background.map_or(0.0, |bg| bg.component(idx, component));
+            }
+            rgb
+        };
+
+        let (w, h) = (region.size().w as usize, region.size().h as usize);
+        let (min_x, min_y, res_w) = (
+            region.min.x as usize,
+            region.min.y as usize,
+            self.res.w as usize,
+        );
+        // The index into the film of pixel `(x, y)` of the region, counting rows from the bottom.
+        let film_index = move |x: usize, y: usize| (min_x + x) + (min_y + y) * res_w;
+
+        println!("Saving to {}...", filename.display());
+        let file = File::create(filename)
+            .map_err(|e| format!("Failed to create {}: {}", filename.display(), e))?;
+        let mut writer = BufWriter::new(file);
+
+        let result = match format {
+            OutputFormat::Hdr => {
+                // Radiance files store rows top to bottom, while the film stores them bottom to top.
+                let data = (0..h)
+                    .flat_map(|y| (0..w).map(move |x| film_index(x, h - 1 - y)))
+                    .map(|idx| image::Rgb(pixel(idx)))
+                    .collect::<Vec<_>>();
+                image::hdr::HDREncoder::new(writer).encode(&data, w, h)
+            }
+            OutputFormat::Pfm => {
+                // PFM stores rows bottom to top like the film, so no flip is needed. A negative
+                // scale marks the data as little endian.
+                let grey = components == 1;
+                let header = format!("{}\n{} {}\n-1.0\n", if grey { "Pf" } else { "PF" }, w, h);
+                let mut bytes = Vec::with_capacity(w * h * if grey { 4 } else { 12 });
+                for idx in (0..h).flat_map(|y| (0..w).map(move |x| film_index(x, y))) {
+                    let rgb = pixel(idx);
+                    for value in rgb.iter().take(if grey { 1 } else { 3 }) {
+                        bytes.extend_from_slice(&value.to_le_bytes());
+                    }
+                }
+                writer
+                    .write_all(header.as_bytes())
+                    .and_then(|_| writer.write_all(&bytes))
+                    .and_then(|_| writer.flush())
+            }
+            OutputFormat::Png => unreachable!("PNG output is handled by save_to"),
+        };
+
+        result.map_err(|e| format!("Failed to write {}: {}", filename.display(), e))
+    }
 }
 
 impl<'a, N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>> Film<N> {
+    /// Render one progressive pass (epoch) of `samples` into the film. The samples are added to
+    /// those of previous passes, so the film keeps converging with each call, and its channels
+    /// hold a usable image after each one. Call `reset` before rendering a different frame.
+    ///
+    /// If `crop` is given, only the pixels inside it are rendered and the rest of the film is left
+    /// as it was.
     #[allow(clippy::too_many_arguments)]
-    pub fn render_frame_into<I, F>(
+    pub fn render_frame_into<I, F, S>(
         &'a mut self,
         world: &World,
         camera: CameraHandle,
@@ -388,18 +1923,38 @@ impl<'a, N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>> Film<
         tile_size: Extent2u,
         frame: usize,
         time_range: Range<f32>,
-        samples: usize,
+        samples: S,
+        crop: Option<CropWindow>,
     ) where
         F: Filter + Copy + Send,
-        I: Integrator,
+        I: Integrator + ?Sized,
+        S: Into<SampleBudget>,
     {
+        if let Some(previous) = self.frame {
+            if previous != frame {
+                panic!(
+                    "Film is accumulating frame {}, but frame {} was rendered into it. Reset it first.",
+                    previous, frame
+                );
+            }
+        }
+        self.frame = Some(frame);
+
+        // Tiles only resolve their own pixels, so undo any post processing everywhere first.
+        if self.post_processed {
+            self.resolve_all();
+        }
+
+        let budget = samples.into();
         let camera = world.cameras.get(camera);
         let mut tiles = Vec::new();
 
         let rem = Vec2u::new((self.res.w) % tile_size.w, (self.res.h) % tile_size.h);
-        {
+        let crop_bounds = crop.map(|crop| crop.raster_bounds(self.res));
+        let prior_max_count = {
             let mut idx = 0;
             let channels = self.channels.lock().unwrap();
+            let accumulated = self.accumulated.lock().unwrap();
             for tile_x in 0..((self.res.w + rem.x) / tile_size.w) {
                 for tile_y in 0..((self.res.h + rem.y) / tile_size.h) {
                     let start = Vec2u::new(tile_x * tile_size.w, tile_y * tile_size.h);
@@ -407,36 +1962,64 @@ impl<'a, N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>> Film<
                         (start.x + tile_size.w).min(self.res.w),
                         (start.y + tile_size.h).min(self.res.h),
                     );
-                    let tile_bounds = Bounds2u {
+                    let mut tile_bounds = Bounds2u {
                         min: start,
                         max: end,
                     };
 
+                    // Only the part of the tile inside the crop window is rendered.
+                    if let Some(crop_bounds) = crop_bounds {
+                        tile_bounds = tile_bounds.intersect(&crop_bounds);
+                        if tile_bounds.is_empty() {
+                            continue;
+                        }
+                    }
+
                     let tile = Tile::new(
                         idx,
                         self.progressive_epoch,
                         channels.iter().map(|c| c.kind()),
                         self.res,
                         tile_bounds,
+                        accumulated.stats.region(self.res, tile_bounds),
                     );
                     tiles.push(tile);
 
                     idx += 1;
                 }
             }
-        }
+            accumulated.stats.max_count() as usize
+        };
 
         let fis = FilterImportanceSampler::new(filter);
 
-        let sets_1d = 1 + integrator.requested_1d_sample_sets();
-        let sets_2d = 2 + integrator.requested_2d_sample_sets();
-
-        let sample_sets = Samples::new_rd(4 * samples, sets_1d, sets_2d, frame as u64);
-        // let sample_sets = Samples::new_random(4 * samples, sets_1d, sets_2d);
+        // The first two 1d sets are used for the time and wavelengths and the first two 2d sets
+        // for the film position and lens position of camera rays.
+        let sample_layout = SampleLayout::new(
+            integrator.max_depth(),
+            2,
+            2,
+            |depth| integrator.requested_1d_sample_sets(depth),
+            |depth| integrator.requested_2d_sample_sets(depth),
+        );
+        let sets_1d = sample_layout.total_1d();
+        let sets_2d = sample_layout.total_2d();
+
+        // Samples taken in this epoch continue the sequences where previous epochs left off.
+        let max_samples = prior_max_count / 4 + budget.max_samples();
+        let sample_sets = Samples::new_rd(4 * max_samples, sets_1d, sets_2d, frame as u64);
+        // let sample_sets = Samples::new_random(4 * max_samples, sets_1d, sets_2d);
 
         let width = self.res.w;
-
-        self.integrate_tiles(tiles, samples * 4, |tile| {
+        let res = self.res;
+        let record_motion = self
+            .channel_indices
+            .contains_key(&ChannelKind::MotionVector);
+        let record_depth = self.channel_indices.contains_key(&ChannelKind::Depth);
+        let shutter_open = f32x4::from(time_range.start);
+        let shutter_close = f32x4::from(time_range.end);
+
+        self.integrate_tiles(tiles, |tile| {
             // let mut rng = SmallRng::from_rng(thread_rng()).unwrap();
             // let offset = (tile.index as u64) << 32;
 
@@ -447,187 +2030,227 @@ impl<'a, N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>> Film<
             let mut wintersections = BumpVec::new_in(&shading_point_bump);
             let sample_bump = Bump::new();
             let mut new_samples = BumpVec::new_in(&sample_bump);
+            let mut new_splats = BumpVec::new_in(&sample_bump);
             let hit_bump = Bump::new();
             let mut hit_store = HitStore::from_hitable_store(&hit_bump, &world.hitables);
             let mut bsdf_bump = Bump::new();
 
             let time_range_range = f32x4::from(time_range.end - time_range.start);
 
-            for x in tile.raster_bounds.min.x..tile.raster_bounds.max.x {
-                for y in tile.raster_bounds.min.y..tile.raster_bounds.max.y {
-                    let tile_coord = Vec2u::new(x, y) - tile.raster_bounds.min;
+            let mut pass = budget.first_pass();
+            while !pass.is_empty() {
+                for x in tile.raster_bounds.min.x..tile.raster_bounds.max.x {
+                    for y in tile.raster_bounds.min.y..tile.raster_bounds.max.y {
+                        let tile_coord = Vec2u::new(x, y) - tile.raster_bounds.min;
 
-                    let mut rng = SmallRng::seed_from_u64((x + y * width) as u64);
-                    let scramble = rng.gen();
-
-                    for samp in 0..samples {
-                        let sample_nums = [4 * samp, 4 * samp + 1, 4 * samp + 2, 4 * samp + 3];
+                        if budget.converged(tile, tile_coord) {
+                            continue;
+                        }
 
-                        let ndcs = Wec2::from([
-                            sample_uv(
-                                x,
-                                y,
-                                tile.screen_to_ndc_size,
-                                &fis,
-                                &[
-                                    sample_sets.sample_2d(0, sample_nums[0], scramble, 0),
-                                    sample_sets.sample_2d(1, sample_nums[0], scramble, 0),
-                                ],
-                            ),
-                            sample_uv(
-                                x,
-                                y,
-                                tile.screen_to_ndc_size,
-                                &fis,
+                        let mut rng = SmallRng::seed_from_u64((x + y * width) as u64);
+                        let scramble = rng.gen();
+
+                        let prior_samples = tile.prior_sample_count(tile_coord) as usize / 4;
+                        tile.add_camera_samples(tile_coord, 4 * pass.len() as u32);
+
+                        for samp in pass.clone() {
+                            let samp = prior_samples + samp;
+                            let sample_nums = [4 * samp, 4 * samp + 1, 4 * samp + 2, 4 * samp + 3];
+
+                            let ndcs = Wec2::from([
+                                sample_uv(
+                                    x,
+                                    y,
+                                    tile.screen_to_ndc_size,
+                                    &fis,
+                                    &[
+                                        sample_sets.sample_2d(0, sample_nums[0], scramble, 0),
+                                        sample_sets.sample_2d(1, sample_nums[0], scramble, 0),
+                                    ],
+                                ),
+                                sample_uv(
+                                    x,
+                                    y,
+                                    tile.screen_to_ndc_size,
+                                    &fis,
+                                    &[
+                                        sample_sets.sample_2d(0, sample_nums[1], scramble, 0),
+                                        sample_sets.sample_2d(1, sample_nums[1], scramble, 0),
+                                    ],
+                                ),
+                                sample_uv(
+                                    x,
+                                    y,
+                                    tile.screen_to_ndc_size,
+                                    &fis,
+                                    &[
+                                        sample_sets.sample_2d(0, sample_nums[2], scramble, 0),
+                                        sample_sets.sample_2d(1, sample_nums[2], scramble, 0),
+                                    ],
+                                ),
+                                sample_uv(
+                                    x,
+                                    y,
+                                    tile.screen_to_ndc_size,
+                                    &fis,
+                                    &[
+                                        sample_sets.sample_2d(0, sample_nums[3], scramble, 0),
+                                        sample_sets.sample_2d(1, sample_nums[3], scramble, 0),
+                                    ],
+                                ),
+                            ]);
+
+                            let times = f32x4::from(time_range.start)
+                                + time_range_range
+                                // * f32x4::from(rng.gen::<[f32; 4]>());
+                                * sample_sets.wide_sample_1d(sample_nums[0], scramble, 0);
+
+                            let mut rays = camera.get_rays(
+                                scramble,
+                                sample_nums,
+                                tile_coord,
+                                ndcs,
+                                times,
                                 &[
-                                    sample_sets.sample_2d(0, sample_nums[1], scramble, 0),
-                                    sample_sets.sample_2d(1, sample_nums[1], scramble, 0),
+                                    sample_sets.wide_sample_2d(0, sample_nums[0], scramble, 1),
+                                    sample_sets.wide_sample_2d(1, sample_nums[0], scramble, 1),
                                 ],
-                            ),
-                            sample_uv(
-                                x,
-                                y,
-                                tile.screen_to_ndc_size,
-                                &fis,
-                                &[
-                                    sample_sets.sample_2d(0, sample_nums[2], scramble, 0),
-                                    sample_sets.sample_2d(1, sample_nums[2], scramble, 0),
-                                ],
-                            ),
-                            sample_uv(
-                                x,
-                                y,
-                                tile.screen_to_ndc_size,
-                                &fis,
-                                &[
-                                    sample_sets.sample_2d(0, sample_nums[3], scramble, 0),
-                                    sample_sets.sample_2d(1, sample_nums[3], scramble, 0),
-                                ],
-                            ),
-                        ]);
-
-                        let times = f32x4::from(time_range.start)
-                            + time_range_range
-                            // * f32x4::from(rng.gen::<[f32; 4]>());
-                            * sample_sets.wide_sample_1d(sample_nums[0], scramble, 0);
-
-                        let rays = camera.get_rays(
-                            scramble,
-                            sample_nums,
-                            tile_coord,
-                            ndcs,
-                            times,
-                            &[
-                                sample_sets.wide_sample_2d(0, sample_nums[0], scramble, 1),
-                                sample_sets.wide_sample_2d(1, sample_nums[0], scramble, 1),
-                            ],
-                        );
+                            );
+                            rays.wavelengths = spectrum::sample_wavelengths(
+                                sample_sets.wide_sample_1d(sample_nums[0], scramble, 1),
+                            );
 
-                        spawned_wrays.push(rays);
+                            spawned_wrays.push(rays);
+                        }
                     }
                 }
-            }
-
-            for depth in 0.. {
-                bsdf_bump.reset();
 
                 if spawned_wrays.is_empty() {
                     break;
                 }
 
-                hit_store.reset();
-
-                let half_pixel_size_at: Box<dyn Fn(f32x4) -> f32x4> = if depth == 0 {
-                    Box::new(
-                        #[inline]
-                        |t: f32x4| camera.half_pixel_size_at(t),
-                    )
-                // Box::new(|_t| f32x4::from(0.0001))
-                } else {
-                    Box::new(
-                        #[inline]
-                        |t| f32x4::from(0.0001 * 2.0 * depth as f32) * t,
-                    )
-                };
-
-                for wray in spawned_wrays.drain(..) {
-                    world.hitables.add_hits(
-                        wray,
-                        f32x4::from(crate::setup::WORLD_RADIUS * 2.0),
-                        &mut hit_store,
-                        &half_pixel_size_at,
-                    );
-                }
+                for depth in 0.. {
+                    bsdf_bump.reset();
 
-                hit_store.process_hits(&world.hitables, &mut wintersections, &half_pixel_size_at);
+                    if spawned_wrays.is_empty() {
+                        break;
+                    }
 
-                for (mat_id, wshading_point) in wintersections.drain(..) {
-                    let mut samples_1d = [f32x4::ZERO; 3 + VOLUME_MARCHES_PER_SAMPLE];
-                    let num_1d_samples = samples_1d.len();
+                    hit_store.reset();
+
+                    let half_pixel_size_at: Box<dyn Fn(f32x4) -> f32x4> = if depth == 0 {
+                        Box::new(
+                            #[inline]
+                            |t: f32x4| camera.half_pixel_size_at(t),
+                        )
+                    // Box::new(|_t| f32x4::from(0.0001))
+                    } else {
+                        Box::new(
+                            #[inline]
+                            |t| f32x4::from(0.0001 * 2.0 * depth as f32) * t,
+                        )
+                    };
 
-                    for (set, sample) in samples_1d.iter_mut().enumerate() {
-                        *sample = sample_sets.wide_sample_1d_array(
-                            wshading_point.ray.sample,
-                            wshading_point.ray.scramble,
-                            1 + set + depth * num_1d_samples,
+                    for wray in spawned_wrays.drain(..) {
+                        world.hitables.add_hits(
+                            wray,
+                            f32x4::from(world.radius * 2.0),
+                            &mut hit_store,
+                            &half_pixel_size_at,
                         );
                     }
 
-                    let mut samples_2d = [f32x4::ZERO; 12 + 8 * VOLUME_MARCHES_PER_SAMPLE];
-                    let num_2d_samples = samples_2d.len();
+                    hit_store.process_hits(
+                        &world.hitables,
+                        &mut wintersections,
+                        &half_pixel_size_at,
+                    );
 
-                    for (i, sample) in samples_2d.iter_mut().enumerate() {
-                        let dim = i % 2;
-                        let set = i / 2;
+                    for (mat_id, wshading_point) in wintersections.drain(..) {
+                        if depth == 0 && record_motion {
+                            record_motion_vectors(
+                                world,
+                                camera,
+                                &wshading_point,
+                                shutter_open,
+                                shutter_close,
+                                res,
+                                &mut new_samples,
+                            );
+                        }
+                        if depth == 0 && record_depth {
+                            record_depths(
+                                world,
+                                camera,
+                                mat_id,
+                                &wshading_point,
+                                &bsdf_bump,
+                                &mut new_samples,
+                            );
+                        }
 
-                        *sample = sample_sets.wide_sample_2d_array(
-                            dim,
+                        let sample_provider = sample_layout.provider(
+                            &sample_sets,
+                            depth,
                             wshading_point.ray.sample,
                             wshading_point.ray.scramble,
-                            2 + set + depth * num_2d_samples / 2,
                         );
-                    }
 
-                    integrator.integrate(
-                        world,
-                        &samples_1d,
-                        &samples_2d,
-                        depth,
-                        mat_id,
-                        wshading_point,
-                        &bsdf_bump,
-                        &mut spawned_rays,
-                        &mut new_samples,
-                    );
-                }
+                        integrator.integrate(
+                            world,
+                            &sample_provider,
+                            depth,
+                            mat_id,
+                            wshading_point,
+                            &bsdf_bump,
+                            &mut spawned_rays,
+                            &mut new_samples,
+                            &mut new_splats,
+                        );
+                    }
 
-                for (tile_coord, sample) in new_samples.drain(..) {
-                    tile.add_sample(tile_coord, sample);
-                }
+                    for (tile_coord, sample) in new_samples.drain(..) {
+                        tile.add_sample(tile_coord, sample);
+                    }
 
-                while spawned_rays.len() % 4 != 0 {
-                    spawned_rays.push(Ray::new_invalid());
-                }
+                    for (uv, color) in new_splats.drain(..) {
+                        if let Some(idx) = splat_index(uv, res, crop_bounds) {
+                            tile.splats.push((idx, color));
+                        }
+                    }
 
-                for rays in spawned_rays[0..].chunks_exact(4) {
-                    // Safe because we just ensured that it has the correct length
-                    let wray = WRay::from(unsafe {
-                        [
-                            *rays.get_unchecked(0),
-                            *rays.get_unchecked(1),
-                            *rays.get_unchecked(2),
-                            *rays.get_unchecked(3),
-                        ]
-                    });
+                    while spawned_rays.len() % 4 != 0 {
+                        spawned_rays.push(Ray::new_invalid());
+                    }
 
-                    spawned_wrays.push(wray);
+                    for rays in spawned_rays[0..].chunks_exact(4) {
+                        // Safe because we just ensured that it has the correct length
+                        let wray = WRay::from(unsafe {
+                            [
+                                *rays.get_unchecked(0),
+                                *rays.get_unchecked(1),
+                                *rays.get_unchecked(2),
+                                *rays.get_unchecked(3),
+                            ]
+                        });
+
+                        spawned_wrays.push(wray);
+                    }
+                    spawned_rays.clear();
                 }
-                spawned_rays.clear();
+
+                pass = budget.next_pass(pass);
             }
         });
+
+        // Splats land outside the tiles which took them, so resolve everything again once all the
+        // tiles have added theirs.
+        if self.accumulated.get_mut().unwrap().splatted {
+            self.resolve_all();
+        }
     }
 
-    fn integrate_tiles<FN>(&mut self, tiles: Vec<Tile<N>>, samples: usize, integrate_tile: FN)
+    fn integrate_tiles<FN>(&mut self, tiles: Vec<Tile<N>>, integrate_tile: FN)
     where
         FN: FnOnce(&mut Tile<N>) + Send + Sync + Copy,
     {
@@ -643,7 +2266,7 @@ impl<'a, N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>> Film<
                     scope.spawn_fifo(move |_| {
                         integrate_tile(&mut tile);
 
-                        this.tile_finished(tile, samples, pb)
+                        this.tile_finished(tile, pb)
                     })
                 }
             });
@@ -657,12 +2280,7 @@ impl<'a, N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>> Film<
         self.progressive_epoch += 1;
     }
 
-    fn tile_finished(
-        &self,
-        tile: Tile<N>,
-        samples: usize,
-        pb: Arc<Mutex<pbr::ProgressBar<std::io::Stdout>>>,
-    ) {
+    fn tile_finished(&self, tile: Tile<N>, pb: Arc<Mutex<pbr::ProgressBar<std::io::Stdout>>>) {
         if self.progressive_epoch != tile.epoch {
             panic!(
                 "Epoch mismatch! Expected: {}, got: {}",
@@ -673,22 +2291,195 @@ impl<'a, N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>> Film<
         let mut pb = pb.lock().unwrap();
         pb.inc();
 
-        let mut channels = self.channels.lock().unwrap();
-
         let Tile {
             channels: tile_channels,
+            stats,
             raster_bounds: tile_bounds,
+            splats,
             ..
         } = tile;
 
-        for (tile_channel, channel) in tile_channels.iter().zip(channels.iter_mut()) {
-            // Safe because we guarantee that we won't start modifying this chunk again
-            // until the next epoch.
+        // Tiles don't overlap, so merging the tile only touches its own pixels, which no other
+        // tile will modify until the next epoch. Splats can land anywhere, but only ever add up.
+        let mut accumulated = self.accumulated.lock().unwrap();
+        accumulated.stats.add_tile(&stats, self.res, tile_bounds);
+        accumulated.camera_samples += stats.counts.iter().map(|&c| c as u64).sum::<u64>();
+        accumulated.splatted |= !splats.is_empty();
+        for (idx, color) in splats {
+            accumulated.splats[idx] += color;
+        }
+
+        let accumulated = &mut *accumulated;
+        let mut channels = self.channels.lock().unwrap();
+        for ((tile_channel, sum), channel) in tile_channels
+            .iter()
+            .zip(accumulated.sums.iter_mut())
+            .zip(channels.iter_mut())
+        {
+            sum.add_tile(tile_channel, self.res, tile_bounds).unwrap();
             channel
-                .copy_from_tile(tile_channel, self.res, tile_bounds, samples)
+                .resolve_from(sum, &accumulated.stats, self.res, tile_bounds)
                 .unwrap();
+            accumulated.resolve_splats(channel, self.res, tile_bounds);
+        }
+    }
+}
+
+/// Records the screen space motion, in pixels with +y up, of the first hit points of `intersection`
+/// between shutter open and shutter close, due to both the camera and the hitable moving.
+fn record_motion_vectors(
+    world: &World,
+    camera: &dyn Camera,
+    intersection: &WShadingPoint,
+    shutter_open: f32x4,
+    shutter_close: f32x4,
+    res: Extent2u,
+    output_samples: &mut BumpVec<(Vec2u, ChannelSample)>,
+) {
+    let hitable = &world.hitables[intersection.object_id];
+    let time = intersection.ray.time;
+    let point_open = hitable.move_point(intersection.point, time, shutter_open);
+    let point_close = hitable.move_point(intersection.point, time, shutter_close);
+
+    let uv_open = camera.project(point_open, shutter_open);
+    let uv_close = camera.project(point_close, shutter_close);
+
+    let motion = Wec2::new(
+        (uv_close.x - uv_open.x) * f32x4::from(res.w as f32),
+        (uv_close.y - uv_open.y) * f32x4::from(res.h as f32),
+    );
+    let motions: [Vec2; 4] = motion.into();
+    let rays: [Ray; 4] = intersection.ray.into();
+
+    for (ray, motion) in rays.iter().zip(motions.iter()) {
+        // Points in the plane of the camera can't be projected.
+        if ray.valid && motion.x.is_finite() && motion.y.is_finite() {
+            output_samples.push((ray.tile_coord, ChannelSample::MotionVector(*motion)));
+        }
+    }
+}
+
+/// Record the camera space depth of the first hits of camera rays on surfaces receiving light,
+/// which is their distance from the camera along its viewing direction rather than along the ray.
+fn record_depths(
+    world: &World,
+    camera: &dyn Camera,
+    material: MaterialHandle,
+    intersection: &WShadingPoint,
+    bump: &Bump,
+    output_samples: &mut BumpVec<(Vec2u, ChannelSample)>,
+) {
+    let receives_light = world
+        .materials
+        .get(material)
+        .get_bsdf_at(intersection, bump)
+        .receives_light();
+    if !receives_light {
+        return;
+    }
+
+    // The camera looks down the negative w axis of its basis, and every ray leaves from the plane
+    // of its lens, so the depth is how far the hit is from the ray's origin along that axis.
+    let forward = -camera.basis(intersection.ray.time).cols[2];
+    let depth = (intersection.point - intersection.ray.origin).dot(forward);
+    let depths: [f32; 4] = depth.into();
+    let rays: [Ray; 4] = intersection.ray.into();
+
+    for (ray, depth) in rays.iter().zip(depths.iter()) {
+        if ray.valid {
+            output_samples.push((ray.tile_coord, ChannelSample::Depth(*depth)));
+        }
+    }
+}
+
+/// Write display encoded values in `[0, 1]`, `components` (3 for RGB or 4 for RGBA) per pixel
+/// with rows top to bottom, to a PNG at `filename` tagged with `output`'s color space. HDR outputs
+/// are written with 16 bits per component, since 8 bits visibly band across PQ's range.
+fn save_display_png(
+    filename: &std::path::Path,
+    size: Extent2u,
+    components: usize,
+    values: &[f32],
+    output: OutputTransform,
+) -> Result<(), String> {
+    let (bits, data) = if output.is_hdr() {
+        let mut data = Vec::with_capacity(values.len() * 2);
+        for value in values.iter() {
+            let value = (value.max(0.0).min(1.0) * 65535.0).round() as u16;
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        (16, data)
+    } else {
+        let data = values
+            .iter()
+            .map(|value| (value * 255.0).min(255.0).max(0.0) as u8)
+            .collect::<Vec<_>>();
+        (8, data)
+    };
+    let color = match components {
+        4 => image::ColorType::RGBA(bits),
+        _ => image::ColorType::RGB(bits),
+    };
+
+    let mut png = Vec::new();
+    image::png::PNGEncoder::new(&mut png)
+        .encode(&data, size.w, size.h, color)
+        .map_err(|e| format!("Failed to encode {}: {}", filename.display(), e))?;
+
+    // The cICP chunk has to come before the image data, so it goes right after the header chunk,
+    // which always takes the 25 bytes after the 8 byte signature.
+    let header_end = 8 + 25;
+    let cicp = png_chunk(b"cICP", &output.cicp());
+    png.splice(header_end..header_end, cicp);
+
+    std::fs::write(filename, png)
+        .map_err(|e| format!("Failed to write {}: {}", filename.display(), e))
+}
+
+/// A PNG chunk of type `kind` holding `data`, with its length and CRC.
+fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(12 + data.len());
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = png_crc(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    chunk
+}
+
+/// The CRC-32 of PNG chunks, over their type and data.
+fn png_crc(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
         }
     }
+    !crc
+}
+
+/// The index into the film of the pixel at `uv`, if it's on the film and inside the crop window.
+fn splat_index(uv: Vec2, res: Extent2u, crop_bounds: Option<Bounds2u>) -> Option<usize> {
+    let bounds = crop_bounds.unwrap_or(Bounds2u {
+        min: Vec2u::new(0, 0),
+        max: Vec2u::new(res.w, res.h),
+    });
+    let x = (uv.x * res.w as f32).floor();
+    let y = (uv.y * res.h as f32).floor();
+    // Also rejects NaNs.
+    if !(x >= bounds.min.x as f32
+        && x < bounds.max.x as f32
+        && y >= bounds.min.y as f32
+        && y < bounds.max.y as f32)
+    {
+        return None;
+    }
+    Some(x as usize + y as usize * res.w as usize)
 }
 
 #[inline]
@@ -707,3 +2498,261 @@ fn sample_uv(
 
     screen_to_ndc_size * screen_coord
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{CameraStore, PinholeCamera};
+    use crate::filter::BoxFilter;
+    use crate::hitable::HitableStore;
+    use crate::integrator::{
+        BidirectionalIntegrator, LightTracingIntegrator, PathTracingIntegrator,
+    };
+    use crate::light::{Light, SphereLight};
+    use crate::material::{Lambertian, MaterialStore, Sky};
+    use crate::spectrum::WSrgb;
+    use crate::sphere::Sphere;
+    use crate::volume::VolumeParams;
+
+    use generic_array::typenum::{U1, U2};
+
+    const RES: Extent2u = Extent2u::new(32, 32);
+
+    /// A grey ball on a grey floor which fills the view, lit only by a small light above them, so
+    /// that paths traced from the camera and from the light see the same scene.
+    fn lit_ball() -> (World, CameraHandle) {
+        let mut materials = MaterialStore::new();
+        let grey = materials.add_material(Lambertian::new(WSrgb::splat(Srgb::new(0.5, 0.5, 0.5))));
+        let sky = materials.add_material(Sky::new(Srgb::zero(), Srgb::zero()));
+
+        let mut hitables = HitableStore::new();
+        hitables.push(Sphere::new(Vec3::zero(), 100.0, sky));
+        hitables.push(Sphere::new(Vec3::new(0.0, -51.0, 0.0), 50.0, grey));
+        hitables.push(Sphere::new(Vec3::zero(), 0.5, grey));
+
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(SphereLight::new(
+            Vec3::new(0.5, 2.0, 0.5),
+            0.2,
+            Srgb::new(20.0, 20.0, 20.0),
+        ))];
+
+        let mut cameras = CameraStore::new();
+        let camera = cameras.add_camera(Box::new(PinholeCamera::new(
+            Vec2::new(RES.w as f32, RES.h as f32),
+            40.0,
+            Vec3::new(0.0, 2.0, 3.0),
+            Vec3::new(0.0, -0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        )));
+
+        let world = World {
+            hitables,
+            lights,
+            materials,
+            cameras,
+            volume_params: VolumeParams {
+                coeff_scattering: None,
+                coeff_extinction: None,
+                extinction_color: Srgb::one(),
+            },
+            radius: 100.0,
+        };
+        (world, camera)
+    }
+
+    /// The mean of the Color channel's green component over the image rendered with
+    /// `integrator`.
+    fn mean_color<I: Integrator>(world: &World, camera: CameraHandle, integrator: &I) -> f32 {
+        let mut film = Film::<U1>::new(&[ChannelKind::Color], RES).unwrap();
+        film.render_frame_into(
+            world,
+            camera,
+            integrator,
+            &BoxFilter::default(),
+            Extent2u::new(16, 16),
+            0,
+            0.0..1.0,
+            64,
+            None,
+        );
+
+        let channels = film.channels.lock().unwrap();
+        let pixels = (RES.w * RES.h) as usize;
+        (0..pixels)
+            .map(|idx| channels[0].component(idx, 1))
+            .sum::<f32>()
+            / pixels as f32
+    }
+
+    #[test]
+    fn splatted_paths_are_as_bright_as_camera_paths() {
+        let (world, camera) = lit_ball();
+        let max_bounces = 2;
+
+        let path = mean_color(
+            &world,
+            camera,
+            &PathTracingIntegrator {
+                max_bounces,
+                volume_marches: 0,
+                spectral: false,
+            },
+        );
+        assert!(path > 0.0);
+
+        let light = mean_color(
+            &world,
+            camera,
+            &LightTracingIntegrator {
+                max_bounces,
+                camera,
+                spectral: false,
+            },
+        );
+        assert!(
+            (light - path).abs() < 0.05 * path,
+            "light tracer: {}, path tracer: {}",
+            light,
+            path
+        );
+
+        let bidirectional = mean_color(
+            &world,
+            camera,
+            &BidirectionalIntegrator {
+                max_bounces,
+                camera,
+                spectral: false,
+            },
+        );
+        assert!(
+            (bidirectional - path).abs() < 0.05 * path,
+            "bidirectional: {}, path tracer: {}",
+            bidirectional,
+            path
+        );
+    }
+
+    const CHANNELS: [ChannelKind; 2] = [ChannelKind::Color, ChannelKind::Alpha];
+
+    /// A film with a few bidirectional samples rendered into it, so that it has sample
+    /// statistics, channel sums and splats.
+    fn rendered_film(world: &World, camera: CameraHandle) -> Film<U2> {
+        let mut film = Film::<U2>::new(&CHANNELS, RES).unwrap();
+        film.render_frame_into(
+            world,
+            camera,
+            &BidirectionalIntegrator {
+                max_bounces: 2,
+                camera,
+                spectral: false,
+            },
+            &BoxFilter::default(),
+            Extent2u::new(16, 16),
+            3,
+            0.0..1.0,
+            4,
+            None,
+        );
+        film
+    }
+
+    /// Everything `film` has accumulated, as the bits of each number.
+    fn accumulated_bits<N>(film: &mut Film<N>) -> Vec<u32>
+    where
+        N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>,
+    {
+        let accumulated = film.accumulated.get_mut().unwrap();
+        let stats = &accumulated.stats;
+        let mut bits = vec![
+            accumulated.camera_samples as u32,
+            accumulated.splatted as u32,
+        ];
+        for idx in 0..stats.counts.len() {
+            bits.push(stats.counts[idx]);
+            for value in [
+                stats.sums[idx],
+                stats.sums_sq[idx],
+                accumulated.splats[idx].0,
+            ]
+            .iter()
+            {
+                bits.extend((0..3).map(|component| value.component(component).to_bits()));
+            }
+        }
+        for sum in accumulated.sums.iter() {
+            let components = sum.kind().component_names().len();
+            for idx in 0..sum.len() {
+                bits.extend(
+                    (0..components).map(|component| sum.component(idx, component).to_bits()),
+                );
+            }
+        }
+        bits
+    }
+
+    fn checkpoint_path(test: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rayn_{}_{}.checkpoint", test, std::process::id()))
+    }
+
+    #[test]
+    fn checkpoints_restore_everything_accumulated() {
+        let (world, camera) = lit_ball();
+        let mut film = rendered_film(&world, camera);
+        assert!(film.accumulated.get_mut().unwrap().splatted);
+
+        let path = checkpoint_path("round_trip");
+        film.save_checkpoint(&path, SampleBudget::Fixed(4), 1)
+            .unwrap();
+        let mut loaded = Film::<U2>::new(&CHANNELS, RES).unwrap();
+        let result = loaded.load_checkpoint(&path, SampleBudget::Fixed(4), 1);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        assert_eq!(accumulated_bits(&mut loaded), accumulated_bits(&mut film));
+        assert_eq!(loaded.epoch(), film.epoch());
+        assert_eq!(loaded.frame(), Some(3));
+    }
+
+    #[test]
+    fn checkpoints_only_continue_the_same_render() {
+        let (world, camera) = lit_ball();
+        let film = rendered_film(&world, camera);
+        let path = checkpoint_path("mismatch");
+        film.save_checkpoint(&path, SampleBudget::Fixed(4), 1)
+            .unwrap();
+
+        let smaller = Film::<U2>::new(&CHANNELS, Extent2u::new(16, 32))
+            .unwrap()
+            .load_checkpoint(&path, SampleBudget::Fixed(4), 1);
+        let more_samples = Film::<U2>::new(&CHANNELS, RES).unwrap().load_checkpoint(
+            &path,
+            SampleBudget::Fixed(8),
+            1,
+        );
+        let more_passes = Film::<U2>::new(&CHANNELS, RES).unwrap().load_checkpoint(
+            &path,
+            SampleBudget::Fixed(4),
+            2,
+        );
+        let reordered = Film::<U2>::new(&[ChannelKind::Alpha, ChannelKind::Color], RES)
+            .unwrap()
+            .load_checkpoint(&path, SampleBudget::Fixed(4), 1);
+        let fewer_channels = Film::<U1>::new(&[ChannelKind::Color], RES)
+            .unwrap()
+            .load_checkpoint(&path, SampleBudget::Fixed(4), 1);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(smaller
+            .unwrap_err()
+            .contains("is 32x32, but the film is 16x32"));
+        assert!(more_samples
+            .unwrap_err()
+            .contains("was rendered with Fixed(4) in 1 passes"));
+        assert!(more_passes
+            .unwrap_err()
+            .contains("was rendered with Fixed(4) in 1 passes"));
+        assert!(reordered.unwrap_err().contains("records the channels"));
+        assert!(fewer_channels.unwrap_err().contains("records the channels"));
+    }
+}
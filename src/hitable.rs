@@ -8,6 +8,25 @@ use bumpalo::Bump;
 pub trait Hitable: Send + Sync {
     /// `hit_threshold_at` is a function which returns the hit threshold at some distance `t` from the start of the ray.
     fn hit(&self, rays: &WRay, t_max: f32x4, hit_threshold_at: &dyn Fn(f32x4) -> f32x4) -> f32x4;
+    /// Like `hit`, but also returns how the search for each hit went, for debugging the tracer.
+    /// The default is for hitables which find their hits exactly, in one step.
+    fn trace_stats(
+        &self,
+        rays: &WRay,
+        t_max: f32x4,
+        hit_threshold_at: &dyn Fn(f32x4) -> f32x4,
+    ) -> WTraceStats {
+        let t = self.hit(rays, t_max, hit_threshold_at);
+        let converged = t.cmp_lt(t_max);
+        WTraceStats {
+            t,
+            steps: f32x4::ONE,
+            surface_dist: f32x4::merge(converged, f32x4::ZERO, f32x4::from(std::f32::INFINITY)),
+            converged,
+            exhausted: f32x4::ZERO,
+            nan: t.cmp_nan(t),
+        }
+    }
     /// return 0 if occluded, 1 if not
     fn occluded(&self, start: Wec3, end: Wec3, time: f32x4) -> f32x4;
     fn get_shading_info(
@@ -15,6 +34,30 @@ pub trait Hitable: Send + Sync {
         hits: WHit,
         half_pixel_size_at: &dyn Fn(f32x4) -> f32x4,
     ) -> (MaterialHandle, WShadingPoint);
+    /// Moves a point on the surface at time `from` to where that part of the surface is at time
+    /// `to`. The default is for hitables which don't move.
+    fn move_point(&self, point: Wec3, _from: f32x4, _to: f32x4) -> Wec3 {
+        point
+    }
+}
+
+/// How the search for the hits of 4 rays went, see `Hitable::trace_stats`. The masks are those
+/// of `f32x4`'s comparisons.
+#[derive(Clone, Copy)]
+pub struct WTraceStats {
+    /// Where along each ray the search stopped, which is what `Hitable::hit` returns.
+    pub t: f32x4,
+    /// The number of steps the search took.
+    pub steps: f32x4,
+    /// How far from the surface the search stopped.
+    pub surface_dist: f32x4,
+    /// The lanes where the search stopped because it got close enough to the surface.
+    pub converged: f32x4,
+    /// The lanes where the search gave up before it either hit the surface or passed `t_max`,
+    /// which are taken as hits wherever the search got to.
+    pub exhausted: f32x4,
+    /// The lanes where the search went NaN.
+    pub nan: f32x4,
 }
 
 #[derive(Clone, Copy)]
@@ -25,6 +68,9 @@ pub struct WShadingPoint {
     pub offset_by: f32x4,
     pub normal: Wec3,
     pub basis: Wat3,
+    /// The index in the `HitableStore` of the hitable which was hit. Filled in by
+    /// `HitStore::process_hits`.
+    pub object_id: usize,
 }
 
 impl WShadingPoint {
@@ -36,6 +82,7 @@ impl WShadingPoint {
             offset_by,
             normal,
             basis: normal.get_orthonormal_basis(),
+            object_id: 0,
         }
     }
 
@@ -87,6 +134,9 @@ impl<'bump> HitStore<'bump> {
         Self { hits }
     }
 
+    /// # Safety
+    ///
+    /// `obj_id` must be the index of a hitable in the `HitableStore` this was created from.
     pub unsafe fn add_hit(&mut self, obj_id: usize, hit: Hit) {
         self.hits.get_unchecked_mut(obj_id).push(hit);
     }
@@ -125,10 +175,10 @@ impl<'bump> HitStore<'bump> {
                         *hits.get_unchecked(3),
                     ]
                 });
-                wintersections.push(
-                    unsafe { hitables.get_unchecked(obj_id) }
-                        .get_shading_info(hits, half_pixel_size_at),
-                );
+                let (material, mut shading_point) = unsafe { hitables.get_unchecked(obj_id) }
+                    .get_shading_info(hits, half_pixel_size_at);
+                shading_point.object_id = obj_id;
+                wintersections.push((material, shading_point));
             }
         }
     }
@@ -152,6 +202,12 @@ impl HitableStore {
     }
 }
 
+impl Default for HitableStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ::std::ops::Deref for HitableStore {
     type Target = Vec<Box<dyn Hitable>>;
 
@@ -167,14 +223,90 @@ impl HitableStore {
         })
     }
 
-    pub fn add_hits(
+    /// Trace 4 rays like `add_hits` does, returning how the search for the closest hits went
+    /// rather than the hits. The steps taken are summed over all the hitables searched.
+    pub fn trace_stats(
         &self,
         ray: WRay,
         t_max: f32x4,
-        hit_store: &mut HitStore,
         half_pixel_size_at: &dyn Fn(f32x4) -> f32x4,
-    ) {
-        let (ids, dists) = self.iter().enumerate().fold(
+    ) -> WTraceStats {
+        let missed = WTraceStats {
+            t: t_max,
+            steps: f32x4::ZERO,
+            surface_dist: f32x4::from(std::f32::INFINITY),
+            converged: f32x4::ZERO,
+            exhausted: f32x4::ZERO,
+            nan: f32x4::ZERO,
+        };
+        self.iter().fold(missed, |closest, hitable| {
+            let stats = hitable.trace_stats(&ray, closest.t, half_pixel_size_at);
+            let closer = stats.t.cmp_lt(closest.t);
+            WTraceStats {
+                t: f32x4::merge(closer, stats.t, closest.t),
+                steps: closest.steps + stats.steps,
+                surface_dist: f32x4::merge(closer, stats.surface_dist, closest.surface_dist),
+                converged: f32x4::merge(closer, stats.converged, closest.converged),
+                exhausted: f32x4::merge(closer, stats.exhausted, closest.exhausted),
+                nan: closest.nan | stats.nan,
+            }
+        })
+    }
+
+    /// Find the closest hits of 4 rays which each follow a path of their own, rather than being
+    /// part of the film's wavefront of rays, so each lane may hit a different hitable. Returns the
+    /// material each lane hit, or `None` where it missed everything, and the shading point of the
+    /// hits, whose lanes are meaningless where the ray missed. Its `object_id` is that of the first
+    /// lane which hit something.
+    pub fn closest_hits(
+        &self,
+        ray: WRay,
+        t_max: f32x4,
+        half_pixel_size_at: &dyn Fn(f32x4) -> f32x4,
+    ) -> ([Option<MaterialHandle>; 4], WShadingPoint) {
+        let (ids, t) = self.closest(ray, t_max, half_pixel_size_at);
+        let hit = WHit { ray, t };
+
+        // Each hitable which was hit shades all 4 lanes, and its results are kept in the lanes
+        // which hit it.
+        let mut materials = [None; 4];
+        let mut point = hit.point();
+        let mut offset_by = f32x4::ZERO;
+        let mut normal = -ray.dir;
+        let mut object_id = None;
+        let id_lanes = f32x4::from([ids[0] as f32, ids[1] as f32, ids[2] as f32, ids[3] as f32]);
+        for (lane, &id) in ids.iter().enumerate() {
+            if id == std::usize::MAX || ids[..lane].contains(&id) {
+                continue;
+            }
+
+            let (material, shading_point) = self[id].get_shading_info(hit, half_pixel_size_at);
+            let mask = id_lanes.cmp_eq(f32x4::from(id as f32));
+            point = Wec3::merge(mask, shading_point.point, point);
+            offset_by = f32x4::merge(mask, shading_point.offset_by, offset_by);
+            normal = Wec3::merge(mask, shading_point.normal, normal);
+            for (other, material_lane) in materials.iter_mut().enumerate().skip(lane) {
+                if ids[other] == id {
+                    *material_lane = Some(material);
+                }
+            }
+            object_id = object_id.or(Some(id));
+        }
+
+        let mut shading_point = WShadingPoint::new(hit, point, offset_by, normal);
+        shading_point.object_id = object_id.unwrap_or(0);
+        (materials, shading_point)
+    }
+
+    /// The index of the closest hitable each of 4 rays hits before `t_max`, `usize::MAX` where
+    /// they hit nothing, and how far along the rays the hits are.
+    fn closest(
+        &self,
+        ray: WRay,
+        t_max: f32x4,
+        half_pixel_size_at: &dyn Fn(f32x4) -> f32x4,
+    ) -> ([usize; 4], f32x4) {
+        self.iter().enumerate().fold(
             ([std::usize::MAX; 4], t_max),
             |acc, (hitable_id, hitable)| {
                 let (mut closest_ids, mut closest) = acc;
@@ -195,7 +327,17 @@ impl HitableStore {
 
                 (closest_ids, closest)
             },
-        );
+        )
+    }
+
+    pub fn add_hits(
+        &self,
+        ray: WRay,
+        t_max: f32x4,
+        hit_store: &mut HitStore,
+        half_pixel_size_at: &dyn Fn(f32x4) -> f32x4,
+    ) {
+        let (ids, dists) = self.closest(ray, t_max, half_pixel_size_at);
 
         let rays: [Ray; 4] = ray.into();
         let dists = dists.as_ref();
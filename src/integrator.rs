@@ -1,79 +1,220 @@
 use bumpalo::collections::Vec as BumpVec;
 use bumpalo::Bump;
 
+use crate::camera::{Camera, CameraHandle};
 use crate::film::ChannelSample;
 use crate::hitable::WShadingPoint;
-use crate::material::{MaterialHandle, BSDF};
-use crate::math::{f32x4, Vec2u, Vec3, Wec3};
-use crate::ray::Ray;
-use crate::spectrum::{Srgb, WSrgb};
+use crate::light::{Light, WEmission};
+use crate::material::{MaterialHandle, WScatteringEvent, BSDF};
+use crate::math::{
+    f32x4, power_heuristic, OrthonormalBasis, RandomSample3d, Vec2, Vec2u, Vec3, Wec2, Wec3,
+};
+use crate::ray::{Ray, WRay};
+use crate::sampler::WSampleProvider;
+use crate::spectrum::{self, Srgb, WSrgb};
 use crate::world::World;
-use crate::setup::VOLUME_MARCHES_PER_SAMPLE;
 
 pub trait Integrator: Send + Sync {
+    /// Shade the hits of 4 rays at bounce `depth`, pushing the rays to trace at the next depth to
+    /// `spawned_rays` and samples of the pixels the rays were traced for to `output_samples`.
+    ///
+    /// `output_splats` takes samples of the Color channel at any uv position on the film, for
+    /// paths which reach the camera from the scene rather than leave it. The film adds them up
+    /// and scales them by its number of pixels over the number of camera samples taken, so each
+    /// camera sample may trace one such path which estimates the whole image.
     #[allow(clippy::too_many_arguments)]
     fn integrate(
         &self,
         world: &World,
-        samples_1d: &[f32x4; 3 + VOLUME_MARCHES_PER_SAMPLE],
-        samples_2d: &[f32x4; 12 + 8 * VOLUME_MARCHES_PER_SAMPLE],
+        samples: &WSampleProvider,
         depth: usize,
         material: MaterialHandle,
         intersection: WShadingPoint,
         bump: &Bump,
         spawned_rays: &mut BumpVec<Ray>,
         output_samples: &mut BumpVec<(Vec2u, ChannelSample)>,
+        output_splats: &mut BumpVec<(Vec2, Srgb)>,
     );
 
-    fn requested_1d_sample_sets(&self) -> usize;
-    fn requested_2d_sample_sets(&self) -> usize;
+    /// The deepest bounce depth `integrate` will be called with, i.e. the integrator must not spawn
+    /// rays from this depth.
+    fn max_depth(&self) -> usize;
+
+    /// The number of 1d sample sets the integrator will use at `depth`
+    fn requested_1d_sample_sets(&self, depth: usize) -> usize;
+    /// The number of 2d sample sets the integrator will use at `depth`
+    fn requested_2d_sample_sets(&self, depth: usize) -> usize;
 }
 
 #[derive(Clone, Copy)]
 pub struct PathTracingIntegrator {
     pub max_bounces: usize,
     pub volume_marches: usize,
+    /// Trace the wavelengths carried by each ray instead of RGB, uplifting the RGB colors of
+    /// materials and lights to spectra, and convert the result back to RGB when it is recorded.
+    /// The volume's extinction is applied per wavelength, see `VolumeParams::extinction_color`.
+    pub spectral: bool,
+}
+
+impl PathTracingIntegrator {
+    /// The wavelengths the rays at `intersection` carry, if rendering spectrally.
+    fn wavelengths(&self, intersection: &WShadingPoint) -> Option<Wec3> {
+        if self.spectral {
+            Some(intersection.ray.wavelengths)
+        } else {
+            None
+        }
+    }
+
+    /// The color a finished ray records.
+    fn output_radiance(&self, ray: &Ray) -> Srgb {
+        if self.spectral {
+            spectrum::spectral_to_rgb(ray.radiance, ray.wavelengths)
+        } else {
+            ray.radiance
+        }
+    }
+}
+
+/// Uplift an RGB color to the spectrum at `wavelengths` if rendering spectrally.
+fn spectrum_at(rgb: WSrgb, wavelengths: Option<Wec3>) -> WSrgb {
+    match wavelengths {
+        Some(wavelengths) => spectrum::uplift(rgb, wavelengths),
+        None => rgb,
+    }
+}
+
+/// Record the feature channels of the first hits of camera rays.
+fn record_first_hit(
+    intersection: &WShadingPoint,
+    material_id: f32,
+    albedo: WSrgb,
+    output_samples: &mut BumpVec<(Vec2u, ChannelSample)>,
+) {
+    let rays: [Ray; 4] = intersection.ray.into();
+    let albedos: [Srgb; 4] = albedo.into();
+    let points: [Vec3; 4] = intersection.point.into();
+    let object_id = intersection.object_id as f32;
+    for ((ray, albedo), point) in rays.iter().zip(albedos.iter()).zip(points.iter()) {
+        if ray.valid {
+            output_samples.push((ray.tile_coord, ChannelSample::Albedo(*albedo)));
+            output_samples.push((ray.tile_coord, ChannelSample::MaterialId(material_id)));
+            output_samples.push((ray.tile_coord, ChannelSample::WorldPosition(*point)));
+            output_samples.push((ray.tile_coord, ChannelSample::ObjectId(object_id)));
+        }
+    }
+}
+
+/// Record the channels of the first hits of camera rays which only surfaces receiving light, as
+/// opposed to the background, have. The film records their Depth itself, since it needs the
+/// camera.
+fn record_first_surface_hit(
+    intersection: &WShadingPoint,
+    output_samples: &mut BumpVec<(Vec2u, ChannelSample)>,
+) {
+    let rays: [Ray; 4] = intersection.ray.into();
+    let normals: [Vec3; 4] = intersection.normal.into();
+    for (ray, normal) in rays.iter().zip(normals.iter()) {
+        if ray.valid {
+            output_samples.push((ray.tile_coord, ChannelSample::Alpha(1.0)));
+            output_samples.push((ray.tile_coord, ChannelSample::WorldNormal(*normal)));
+        }
+    }
 }
 
+/// The fraction of light of each color, or of each of `wavelengths` if rendering spectrally, which
+/// makes it `dist` through the world's volume.
+fn transmission(world: &World, dist: f32x4, wavelengths: Option<Wec3>) -> WSrgb {
+    let volume = &world.volume_params;
+    if let Some(rho_t) = volume.coeff_extinction {
+        let rho_t =
+            spectrum_at(WSrgb::splat(volume.extinction_color), wavelengths) * f32x4::from(-rho_t);
+        WSrgb(rho_t.map(|rho_t| (rho_t * dist).exp()))
+    } else {
+        WSrgb::one()
+    }
+}
+
+// 1d sample sets used by the `PathTracingIntegrator` at each depth. Each volume march uses
+// two more sets after these, one to choose a light and one to choose the distance along the ray.
+const LIGHT_SELECT_1D: usize = 0;
+const FRESNEL_1D: usize = 1;
+const ROULETTE_1D: usize = 2;
+const FIRST_MARCH_1D: usize = 3;
+
+/// The number of lights sampled at each surface: each lane picks one, and they're sampled for all
+/// four lanes.
+const LIGHT_SAMPLES: usize = 4;
+
+// 2d sample sets used by the `PathTracingIntegrator` at each depth. Each volume march uses
+// four more sets after these, one for each light sampled.
+const LIGHT_SAMPLES_2D: usize = 0;
+const SCATTER_2D: usize = 4;
+const FIRST_MARCH_2D: usize = 6;
+
 impl Integrator for PathTracingIntegrator {
-    fn requested_1d_sample_sets(&self) -> usize {
-        (self.max_bounces + 1) * (3 + self.volume_marches)
+    fn max_depth(&self) -> usize {
+        self.max_bounces
+    }
+
+    fn requested_1d_sample_sets(&self, _depth: usize) -> usize {
+        FIRST_MARCH_1D + 2 * self.volume_marches
     }
 
-    fn requested_2d_sample_sets(&self) -> usize {
-        (self.max_bounces + 1) * (12 + 8 * self.volume_marches)
+    fn requested_2d_sample_sets(&self, _depth: usize) -> usize {
+        FIRST_MARCH_2D + 4 * self.volume_marches
     }
 
     fn integrate(
         &self,
         world: &World,
-        samples_1d: &[f32x4; 3 + VOLUME_MARCHES_PER_SAMPLE],
-        samples_2d: &[f32x4; 12 + 8 * VOLUME_MARCHES_PER_SAMPLE],
+        samples: &WSampleProvider,
         depth: usize,
         material: MaterialHandle,
         mut intersection: WShadingPoint,
         bump: &Bump,
         spawned_rays: &mut BumpVec<Ray>,
         output_samples: &mut BumpVec<(Vec2u, ChannelSample)>,
+        _output_splats: &mut BumpVec<(Vec2, Srgb)>,
     ) {
         let wo = -intersection.ray.dir;
+        let material_id = material.0 as f32;
         let material = world.materials.get(material);
 
         let bsdf = material.get_bsdf_at(&intersection, bump);
+        let wavelengths = self.wavelengths(&intersection);
 
-        let volume_transmission = if let Some(rho_t) = world.volume_params.coeff_extinction {
-            (f32x4::from(-rho_t) * intersection.t).exp()
-        } else {
-            f32x4::ONE
-        };
+        if depth == 0 {
+            record_first_hit(
+                &intersection,
+                material_id,
+                bsdf.albedo(wo, &intersection),
+                output_samples,
+            );
+        }
 
-        intersection.ray.radiance +=
-            bsdf.le(wo, &intersection) * intersection.ray.throughput * volume_transmission;
+        let volume_transmission = transmission(world, intersection.t, wavelengths);
+
+        intersection.ray.radiance += spectrum_at(bsdf.le(wo, &intersection), wavelengths)
+            * emission_weight(world, &intersection, depth)
+            * intersection.ray.throughput
+            * volume_transmission;
+
+        // Camera rays don't see lights, but rays scattered by the last surface find them the same
+        // way sampling the lights directly from it does.
+        if depth > 0 {
+            intersection.ray.radiance +=
+                lights_hit(world, &intersection, wavelengths) * intersection.ray.throughput;
+        }
+
+        // Light samples are weighted against the BSDF sampling the same directions, unless this is
+        // the last bounce and the scattered rays won't be traced.
+        let mis = depth < self.max_bounces;
 
         if bsdf.receives_light() && world.lights.len() > 0 {
-            // let light_idx =
-            //     (samples_1d[0].as_ref()[0] * (world.lights.len() as f32)).floor() as usize;
-            let lights_to_sample = (samples_1d[0] * f32x4::from(world.lights.len() as f32)).floor();
+            let lights_to_sample = (samples.sample_1d(LIGHT_SELECT_1D)
+                * f32x4::from(world.lights.len() as f32))
+            .floor();
             let lights_to_sample = lights_to_sample.as_ref().iter().map(|i| *i as usize);
 
             let correction_factor =
@@ -83,9 +224,11 @@ impl Integrator for PathTracingIntegrator {
                 let li = surface_sample_one_light(
                     world,
                     light_idx,
-                    arrayref::array_ref![samples_2d, 0 + i * 2, 2],
+                    &samples.sample_2d(LIGHT_SAMPLES_2D + i),
                     &intersection,
                     bsdf,
+                    wavelengths,
+                    mis,
                 );
 
                 intersection.ray.radiance +=
@@ -97,8 +240,9 @@ impl Integrator for PathTracingIntegrator {
             let rho_s = f32x4::from(rho_s);
 
             for march in 0..self.volume_marches {
+                let light_select = samples.sample_1d(FIRST_MARCH_1D + 2 * march);
                 let lights_to_sample =
-                    (samples_1d[march + 1] * f32x4::from(world.lights.len() as f32)).floor();
+                    (light_select * f32x4::from(world.lights.len() as f32)).floor();
                 let lights_to_sample = lights_to_sample.as_ref().iter().map(|i| *i as usize);
 
                 let correction_factor = f32x4::from(
@@ -111,38 +255,41 @@ impl Integrator for PathTracingIntegrator {
                     let (li, t) = volume_sample_one_light(
                         world,
                         light_idx,
-                        arrayref::array_ref![samples_2d, 8 + 8 * march + i * 2, 2],
-                        samples_1d[1],
+                        &samples.sample_2d(FIRST_MARCH_2D + 4 * march + i),
+                        samples.sample_1d(FIRST_MARCH_1D + 2 * march + 1),
                         intersection.ray.origin,
                         intersection.ray.dir,
                         intersection.t,
                         intersection.ray.time,
+                        wavelengths,
                     );
 
-                    let transmission = if let Some(rho_t) = world.volume_params.coeff_extinction {
-                        (f32x4::from(-rho_t) * t).exp()
-                    } else {
-                        f32x4::ONE
-                    };
-
-                    intersection.ray.radiance +=
-                        li * intersection.ray.throughput * correction_factor * rho_s * transmission;
+                    intersection.ray.radiance += li
+                        * intersection.ray.throughput
+                        * correction_factor
+                        * rho_s
+                        * transmission(world, t, wavelengths);
                 }
             }
         }
 
         if bsdf.receives_light() {
+            let [u0, v0] = samples.sample_2d(SCATTER_2D);
+            let [u1, v1] = samples.sample_2d(SCATTER_2D + 1);
             let se = bsdf.scatter(
                 wo,
                 &intersection,
-                samples_1d[3],
-                arrayref::array_ref![samples_2d, 8 + 8 * self.volume_marches, 4],
+                samples.sample_1d(FRESNEL_1D),
+                &[u0, v0, u1, v1],
             );
 
             let ndl = se.wi.dot(intersection.normal).abs();
 
-            let mut new_throughput =
-                intersection.ray.throughput * volume_transmission * se.f * ndl / se.pdf;
+            let mut new_throughput = intersection.ray.throughput
+                * volume_transmission
+                * spectrum_at(se.f, wavelengths)
+                * ndl
+                / se.pdf;
 
             let roulette_factor = if depth > 2 {
                 let roulette_factor =
@@ -155,28 +302,25 @@ impl Integrator for PathTracingIntegrator {
                 f32x4::ZERO
             };
 
-            let mut new_rays: [Ray; 4] = intersection.create_rays(se.wi).into();
+            let mut new_wrays = intersection.create_rays(se.wi);
+            new_wrays.pdf = se.pdf;
+            let mut new_rays: [Ray; 4] = new_wrays.into();
             let throughputs: [Srgb; 4] = new_throughput.into();
 
             if depth == 0 {
-                let normals: [Vec3; 4] = intersection.normal.into();
-                for (ray, normal) in new_rays.iter().zip(normals.iter()) {
-                    if ray.valid {
-                        output_samples.push((ray.tile_coord, ChannelSample::Alpha(1.0)));
-                        output_samples.push((ray.tile_coord, ChannelSample::WorldNormal(*normal)));
-                    }
-                }
+                record_first_surface_hit(&intersection, output_samples);
             }
 
             for (((ray, new_throughput), roulette_factor), roulette_sample) in new_rays
                 .iter_mut()
                 .zip(throughputs.iter())
                 .zip(roulette_factor.as_ref().iter())
-                .zip(samples_1d[4].as_ref().iter())
+                .zip(samples.sample_1d(ROULETTE_1D).as_ref().iter())
             {
                 if ray.valid {
                     if depth >= self.max_bounces || *roulette_sample < *roulette_factor {
-                        output_samples.push((ray.tile_coord, ChannelSample::Color(ray.radiance)));
+                        let radiance = self.output_radiance(ray);
+                        output_samples.push((ray.tile_coord, ChannelSample::Color(radiance)));
                     } else {
                         if !new_throughput.is_nan() {
                             ray.throughput = *new_throughput;
@@ -191,10 +335,11 @@ impl Integrator for PathTracingIntegrator {
 
             for ray in final_rays.iter() {
                 if ray.valid {
+                    let radiance = self.output_radiance(ray);
                     let sample = if depth == 0 {
-                        ChannelSample::Background(ray.radiance)
+                        ChannelSample::Background(radiance)
                     } else {
-                        ChannelSample::Color(ray.radiance)
+                        ChannelSample::Color(radiance)
                     };
 
                     output_samples.push((ray.tile_coord, sample));
@@ -204,14 +349,20 @@ impl Integrator for PathTracingIntegrator {
     }
 }
 
+/// Sample light `light_idx` from `intersection`. If `mis` is true, the sample is weighted for
+/// combining it with sampling the BSDF, see `lights_hit`.
+#[allow(clippy::too_many_arguments)]
 pub fn surface_sample_one_light(
     world: &World,
     light_idx: usize,
     samples: &[f32x4; 2],
     intersection: &WShadingPoint,
     bsdf: &dyn BSDF,
+    wavelengths: Option<Wec3>,
+    mis: bool,
 ) -> WSrgb {
     let (end_point, li, pdf) = world.lights[light_idx].sample(samples, intersection.point);
+    let li = spectrum_at(li, wavelengths);
 
     let wo = -intersection.ray.dir;
     let wi = end_point - intersection.point;
@@ -227,18 +378,69 @@ pub fn surface_sample_one_light(
         .hitables
         .test_occluded(occlude_point, end_point, intersection.ray.time);
 
-    let f = bsdf.f(wo, wi, intersection.normal) * intersection.normal.dot(wi).max(f32x4::ZERO);
+    let f = spectrum_at(bsdf.f(wo, wi, intersection.normal), wavelengths)
+        * intersection.normal.dot(wi).max(f32x4::ZERO);
 
-    // volume transmission
-    let transmission = if let Some(rho_t) = world.volume_params.coeff_extinction {
-        (f32x4::from(-rho_t) * dist).exp()
+    let transmission = transmission(world, dist, wavelengths);
+
+    let weight = if mis {
+        let light_pdf = pdf / f32x4::from(world.lights.len() as f32);
+        let bsdf_pdf = bsdf.pdf(wo, wi, intersection.normal);
+        power_heuristic(LIGHT_SAMPLES, light_pdf, 1, bsdf_pdf)
     } else {
         f32x4::ONE
     };
 
-    li * f * transmission * occluded / pdf
+    li * f * transmission * occluded * weight / pdf
+}
+
+/// The light arriving at the origin of the ray which led to `intersection` from lights it passed
+/// through before hitting anything, weighted for combining it with the light samples taken at its
+/// origin (Veach 1997).
+pub fn lights_hit(world: &World, intersection: &WShadingPoint, wavelengths: Option<Wec3>) -> WSrgb {
+    let ray = &intersection.ray;
+    let select_pdf = f32x4::from(1.0 / world.lights.len() as f32);
+
+    let mut radiance = WSrgb::zero();
+    for light in world.lights.iter() {
+        let (dist, le) = light.intersect(ray.origin, ray.dir);
+        let visible = dist.cmp_lt(intersection.t);
+        let dist = dist.min(intersection.t);
+
+        let light_pdf = light.pdf(ray.origin, ray.dir) * select_pdf;
+        let weight = power_heuristic(1, ray.pdf, LIGHT_SAMPLES, light_pdf);
+
+        let transmission = transmission(world, dist, wavelengths);
+
+        radiance += WSrgb::merge(
+            visible,
+            spectrum_at(le, wavelengths) * weight * transmission,
+            WSrgb::zero(),
+        );
+    }
+    radiance
 }
 
+/// The weight of the light emitted by the surface at `intersection`. Emissive geometry inside a
+/// light stands in for it: a ray which passed through the light on the way to it already found the
+/// light's radiance in `lights_hit`, so its own emission isn't counted again. Any other emission,
+/// and everything camera rays see, is only found by hitting it and counts fully.
+fn emission_weight(world: &World, intersection: &WShadingPoint, depth: usize) -> f32x4 {
+    if depth == 0 {
+        return f32x4::ONE;
+    }
+
+    let ray = &intersection.ray;
+    let mut weight = f32x4::ONE;
+    for light in world.lights.iter() {
+        let (dist, _) = light.intersect(ray.origin, ray.dir);
+        let stands_in = dist.cmp_lt(intersection.t) & light.contains(intersection.point);
+        weight = f32x4::merge(stands_in, f32x4::ZERO, weight);
+    }
+    weight
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn volume_sample_one_light(
     world: &World,
     light_idx: usize,
@@ -248,6 +450,7 @@ pub fn volume_sample_one_light(
     ray_d: Wec3,
     max_distance: f32x4,
     time: f32x4,
+    wavelengths: Option<Wec3>,
 ) -> (WSrgb, f32x4) {
     let light = &world.lights[light_idx];
 
@@ -257,6 +460,7 @@ pub fn volume_sample_one_light(
     let sampled_point = ray_o + ray_d * vol_sample_dist;
 
     let (end_point, li, light_pdf) = light.sample(light_samples, sampled_point);
+    let li = spectrum_at(li, wavelengths);
 
     let wi = end_point - sampled_point;
     let dist_point_to_light = wi.mag();
@@ -267,15 +471,1424 @@ pub fn volume_sample_one_light(
 
     let f = f32x4::ONE / (f32x4::from(4.0) * f32x4::PI);
 
-    // volume transmission
-    let transmission = if let Some(rho_t) = world.volume_params.coeff_extinction {
-        (f32x4::from(-rho_t) * dist_point_to_light).exp()
-    } else {
-        f32x4::ONE
-    };
+    let transmission = transmission(world, dist_point_to_light, wavelengths);
 
     (
         li * f * transmission * occluded / (vol_sample_pdf * light_pdf),
         vol_sample_dist,
     )
 }
+
+/// A bidirectional path tracer (Veach 1997), for scenes where light mostly reaches what the camera
+/// sees along paths which are hard to find from the camera, like small lights deep inside the
+/// crevices of a fractal. For each camera sample it traces a subpath from the camera and one from
+/// a light, and combines every way of connecting their vertices with multiple importance sampling,
+/// including connecting the light subpath straight to the camera, which splats it into whichever
+/// pixel sees it.
+///
+/// Both subpaths are traced as a whole once the camera rays first hit something, each lane
+/// following its own. Lanes which hit different materials are shaded with each of their BSDFs in
+/// all four lanes, see `LaneBSDFs`, and lanes whose subpath has ended are carried along masked off
+/// until all four have. The multiple importance sampling weights are accumulated along the
+/// subpaths as in "Implementing Vertex Connection and Merging" (Georgiev 2012), in the quantities
+/// `d_vcm` and `d_vc`. Volumes only absorb light, nothing scatters in them, so scenes whose volumes
+/// scatter light aren't rendered correctly; `scenes/mandelbox_clear.ron` is the default scene
+/// without scattering.
+///
+/// The vertices of the paths are kept in the arena the film gives `integrate`, which it only
+/// clears once it moves on to the next bounce, so it holds every path traced for a tile's pass.
+#[derive(Clone, Copy)]
+pub struct BidirectionalIntegrator {
+    /// Paths are as long as those of the `PathTracingIntegrator` with the same `max_bounces`.
+    pub max_bounces: usize,
+    /// The camera the film is rendered with, which light subpaths are connected to.
+    pub camera: CameraHandle,
+    /// Trace wavelengths instead of RGB, see `PathTracingIntegrator::spectral`.
+    pub spectral: bool,
+}
+
+/// A vertex of the light subpaths of 4 lanes, which camera subpath vertices are connected to.
+struct LightVertex<'bump> {
+    point: WShadingPoint,
+    bsdf: LaneBSDFs<'bump>,
+    /// The lanes whose subpath reached the vertex.
+    valid: f32x4,
+    /// The direction toward the previous vertex, where the light comes from.
+    wi: Wec3,
+    /// The light arriving at the vertex over the pdf of the subpath up to it.
+    throughput: WSrgb,
+    /// The number of edges between the light and the vertex.
+    path_length: usize,
+    d_vcm: f32x4,
+    d_vc: f32x4,
+}
+
+/// Evaluate `eval` with each of `items`, keeping the results in the lanes of the item's mask with
+/// `merge`. Lanes which aren't in any item's mask are `zero`.
+fn merge_lanes<I: Copy, T>(
+    items: &[Option<(f32x4, I)>; 4],
+    zero: T,
+    merge: impl Fn(f32x4, T, T) -> T,
+    eval: impl Fn(I) -> T,
+) -> T {
+    items
+        .iter()
+        .flatten()
+        .fold(zero, |acc, &(mask, item)| merge(mask, eval(item), acc))
+}
+
+/// The BSDFs of the lanes of a shading point whose lanes each hit a material of their own, see
+/// `HitableStore::closest_hits`. Each material's BSDF is evaluated in all 4 lanes and its results
+/// are kept in the lanes which hit it, so lanes which hit the same material cost nothing extra.
+#[derive(Clone, Copy)]
+struct LaneBSDFs<'bump> {
+    /// The BSDF of each material which was hit, with the mask of the lanes which hit it.
+    bsdfs: [Option<(f32x4, &'bump dyn BSDF)>; 4],
+    /// The lanes whose BSDF receives light.
+    receives_light: f32x4,
+}
+
+impl<'bump> LaneBSDFs<'bump> {
+    fn new(
+        world: &World,
+        materials: &[Option<MaterialHandle>; 4],
+        point: &WShadingPoint,
+        bump: &'bump Bump,
+    ) -> Self {
+        let id = |material: &Option<MaterialHandle>| material.map_or(-1.0, |m| m.0 as f32);
+        let ids = f32x4::from([
+            id(&materials[0]),
+            id(&materials[1]),
+            id(&materials[2]),
+            id(&materials[3]),
+        ]);
+
+        let mut bsdfs = [None; 4];
+        let mut receives_light = f32x4::ZERO;
+        for (lane, material) in materials.iter().enumerate() {
+            let material = match material {
+                Some(material) if !ids.as_ref()[..lane].contains(&(material.0 as f32)) => *material,
+                _ => continue,
+            };
+
+            let mask = ids.cmp_eq(f32x4::from(material.0 as f32));
+            let bsdf: &dyn BSDF = world.materials.get(material).get_bsdf_at(point, bump);
+            if bsdf.receives_light() {
+                receives_light = receives_light | mask;
+            }
+            bsdfs[lane] = Some((mask, bsdf));
+        }
+
+        Self {
+            bsdfs,
+            receives_light,
+        }
+    }
+
+    fn scatter(
+        &self,
+        wo: Wec3,
+        point: &WShadingPoint,
+        samples_1d: f32x4,
+        samples_2d: &[f32x4; 4],
+    ) -> WScatteringEvent {
+        merge_lanes(
+            &self.bsdfs,
+            WScatteringEvent::default(),
+            |mask, a, b| WScatteringEvent {
+                wi: Wec3::merge(mask, a.wi, b.wi),
+                f: WSrgb::merge(mask, a.f, b.f),
+                pdf: f32x4::merge(mask, a.pdf, b.pdf),
+            },
+            |bsdf| bsdf.scatter(wo, point, samples_1d, samples_2d),
+        )
+    }
+
+    fn f(&self, wo: Wec3, wi: Wec3, n: Wec3) -> WSrgb {
+        merge_lanes(&self.bsdfs, WSrgb::zero(), WSrgb::merge, |bsdf| {
+            bsdf.f(wo, wi, n)
+        })
+    }
+
+    fn pdf(&self, wo: Wec3, wi: Wec3, n: Wec3) -> f32x4 {
+        merge_lanes(&self.bsdfs, f32x4::ZERO, f32x4::merge, |bsdf| {
+            bsdf.pdf(wo, wi, n)
+        })
+    }
+
+    fn le(&self, wo: Wec3, point: &WShadingPoint) -> WSrgb {
+        merge_lanes(&self.bsdfs, WSrgb::zero(), WSrgb::merge, |bsdf| {
+            bsdf.le(wo, point)
+        })
+    }
+}
+
+/// The lights picked by the lanes of a uniform sample, evaluated like `LaneBSDFs`.
+struct LaneLights<'a> {
+    lights: [Option<(f32x4, &'a dyn Light)>; 4],
+}
+
+impl<'a> LaneLights<'a> {
+    /// Each lane picks one of the world's lights uniformly with its lane of `sample`. The world
+    /// must have lights.
+    fn pick(world: &'a World, sample: f32x4) -> Self {
+        let n = world.lights.len();
+        let ids = (sample * f32x4::from(n as f32))
+            .floor()
+            .min(f32x4::from((n - 1) as f32));
+
+        let mut lights = [None; 4];
+        for (lane, &id) in ids.as_ref().iter().enumerate() {
+            if !ids.as_ref()[..lane].contains(&id) {
+                let light: &dyn Light = &*world.lights[id as usize];
+                lights[lane] = Some((ids.cmp_eq(f32x4::from(id)), light));
+            }
+        }
+        Self { lights }
+    }
+
+    fn sample(&self, samples: &[f32x4; 2], point: Wec3) -> (Wec3, WSrgb, f32x4) {
+        merge_lanes(
+            &self.lights,
+            (Wec3::zero(), WSrgb::zero(), f32x4::ZERO),
+            |mask, a, b| {
+                (
+                    Wec3::merge(mask, a.0, b.0),
+                    WSrgb::merge(mask, a.1, b.1),
+                    f32x4::merge(mask, a.2, b.2),
+                )
+            },
+            |light| light.sample(samples, point),
+        )
+    }
+
+    fn pdf(&self, point: Wec3, wi: Wec3) -> f32x4 {
+        merge_lanes(&self.lights, f32x4::ZERO, f32x4::merge, |light| {
+            light.pdf(point, wi)
+        })
+    }
+
+    fn sample_emission(&self, point_samples: &[f32x4; 2], dir_samples: &[f32x4; 2]) -> WEmission {
+        merge_lanes(
+            &self.lights,
+            WEmission {
+                point: Wec3::zero(),
+                dir: Wec3::zero(),
+                radiance: WSrgb::zero(),
+                pdf_point: f32x4::ZERO,
+                pdf_dir: f32x4::ZERO,
+                cos_theta: f32x4::ZERO,
+            },
+            |mask, a, b| WEmission {
+                point: Wec3::merge(mask, a.point, b.point),
+                dir: Wec3::merge(mask, a.dir, b.dir),
+                radiance: WSrgb::merge(mask, a.radiance, b.radiance),
+                pdf_point: f32x4::merge(mask, a.pdf_point, b.pdf_point),
+                pdf_dir: f32x4::merge(mask, a.pdf_dir, b.pdf_dir),
+                cos_theta: f32x4::merge(mask, a.cos_theta, b.cos_theta),
+            },
+            |light| light.sample_emission(point_samples, dir_samples),
+        )
+    }
+
+    fn emission_pdf(&self, point: Wec3, dir: Wec3) -> (f32x4, f32x4, f32x4) {
+        merge_lanes(
+            &self.lights,
+            (f32x4::ZERO, f32x4::ZERO, f32x4::ZERO),
+            |mask, a, b| {
+                (
+                    f32x4::merge(mask, a.0, b.0),
+                    f32x4::merge(mask, a.1, b.1),
+                    f32x4::merge(mask, a.2, b.2),
+                )
+            },
+            |light| light.emission_pdf(point, dir),
+        )
+    }
+}
+
+/// The power heuristic with an exponent of 2, like `power_heuristic`, applied to one of the pdf
+/// ratios the bidirectional weights are made of.
+fn mis(pdf_ratio: f32x4) -> f32x4 {
+    pdf_ratio * pdf_ratio
+}
+
+/// A mask of the lanes of `ray` which are valid.
+fn valid_lanes(ray: &WRay) -> f32x4 {
+    let lane = |lane: usize| ray.valid[lane] as u8 as f32;
+    f32x4::from([lane(0), lane(1), lane(2), lane(3)]).cmp_eq(f32x4::ONE)
+}
+
+/// Whether any lane of a mask is set.
+fn any_lane(mask: f32x4) -> bool {
+    mask.move_mask() != 0
+}
+
+/// How far rays following the paths of `BidirectionalIntegrator` and `LightTracingIntegrator`
+/// look for hits in each lane: nowhere in the lanes whose path has ended, so searches stop in
+/// them at once.
+fn path_t_max(world: &World, alive: f32x4) -> f32x4 {
+    f32x4::merge(alive, f32x4::from(world.radius * 2.0), f32x4::ZERO)
+}
+
+/// The point to start rays from `point` in direction `dir` from, offset to avoid shadow acne.
+fn offset_point(point: &WShadingPoint, dir: Wec3) -> Wec3 {
+    point.point + point.normal * point.normal.dot(dir).signum() * point.offset_by
+}
+
+/// The lanes of a color, converted to RGB if they hold the spectra at `wavelengths`.
+fn lanes_rgb(color: WSrgb, wavelengths: Option<Wec3>) -> [Srgb; 4] {
+    let mut colors: [Srgb; 4] = color.into();
+    if let Some(wavelengths) = wavelengths {
+        let wavelengths: [Vec3; 4] = wavelengths.into();
+        for (color, wavelengths) in colors.iter_mut().zip(wavelengths.iter()) {
+            *color = spectrum::spectral_to_rgb(*color, *wavelengths);
+        }
+    }
+    colors
+}
+
+/// Splat the lanes of `color` in `mask` at `uv` on the film, see `lanes_rgb`.
+fn splat_lanes(
+    output_splats: &mut BumpVec<(Vec2, Srgb)>,
+    mask: f32x4,
+    uv: Wec2,
+    color: WSrgb,
+    wavelengths: Option<Wec3>,
+) {
+    let uvs: [Vec2; 4] = uv.into();
+    let colors = lanes_rgb(color, wavelengths);
+    for ((mask, uv), color) in mask.as_ref().iter().zip(uvs.iter()).zip(colors.iter()) {
+        if mask.to_bits() != 0 {
+            output_splats.push((*uv, *color));
+        }
+    }
+}
+
+/// Path vertices connected to the camera, see `connect_to_camera`.
+struct CameraConnection {
+    /// Where on the film the vertex is seen.
+    uv: Wec2,
+    /// The direction from the vertex toward the camera.
+    dir: Wec3,
+    /// The light the vertex sends to the camera, per unit of light arriving at it, scaled so that
+    /// splatting it onto the film estimates the image.
+    contribution: WSrgb,
+    /// The pdf of the camera sampling the vertex, with respect to area.
+    pdf: f32x4,
+    /// The lanes where the camera can see the vertex.
+    visible: f32x4,
+}
+
+/// Connect the vertices of paths traced from a light, where light arrives from direction `wi`, to
+/// points on the camera's lens picked with `lens_samples`.
+#[allow(clippy::too_many_arguments)]
+fn connect_to_camera(
+    world: &World,
+    camera: &dyn Camera,
+    lens_samples: &[f32x4; 2],
+    point: &WShadingPoint,
+    bsdf: &LaneBSDFs,
+    wi: Wec3,
+    time: f32x4,
+    wavelengths: Option<Wec3>,
+) -> CameraConnection {
+    let lens_point = camera.sample_lens(time, lens_samples);
+    let (uv, importance) = camera.importance(lens_point, point.point, time);
+    let dir = lens_point - point.point;
+    let dist = dir.mag();
+    let dir = dir / dist;
+    let cos = point.normal.dot(dir).max(f32x4::ZERO);
+    let pdf = importance * cos / (dist * dist);
+
+    let occluded = world
+        .hitables
+        .test_occluded(offset_point(point, dir), lens_point, time);
+    // The camera's pdf is its importance times the geometry term toward it.
+    let contribution = spectrum_at(bsdf.f(dir, wi, point.normal), wavelengths)
+        * pdf
+        * transmission(world, dist, wavelengths)
+        * occluded;
+
+    CameraConnection {
+        uv,
+        dir,
+        contribution,
+        pdf,
+        visible: pdf.cmp_gt(f32x4::ZERO),
+    }
+}
+
+// Sample sets used by the `BidirectionalIntegrator`, which traces whole paths at depth 0. The 1d
+// sets are one to pick the light the light subpath leaves, then two for each camera subpath
+// vertex, to pick a light to sample and a lobe to scatter by, then one for each light subpath
+// vertex, to pick a lobe. The 2d sets are two for the point and direction the light subpath leaves
+// the light in, then three for each camera subpath vertex, one to sample the light and two to
+// scatter, then three for each light subpath vertex, one to pick a point on the lens and two to
+// scatter.
+const EMIT_LIGHT_1D: usize = 0;
+const EMIT_POINT_2D: usize = 0;
+const EMIT_DIR_2D: usize = 1;
+
+impl BidirectionalIntegrator {
+    /// The most vertices either subpath has, not counting the ones on the camera and the light.
+    fn max_vertices(&self) -> usize {
+        self.max_bounces + 1
+    }
+
+    /// The most edges a path has.
+    fn max_path_length(&self) -> usize {
+        self.max_bounces + 2
+    }
+
+    fn camera_vertex_1d(&self, vertex: usize) -> usize {
+        EMIT_LIGHT_1D + 1 + 2 * vertex
+    }
+
+    fn light_vertex_1d(&self, vertex: usize) -> usize {
+        self.camera_vertex_1d(self.max_vertices()) + vertex
+    }
+
+    fn camera_vertex_2d(&self, vertex: usize) -> usize {
+        EMIT_DIR_2D + 1 + 3 * vertex
+    }
+
+    fn light_vertex_2d(&self, vertex: usize) -> usize {
+        self.camera_vertex_2d(self.max_vertices()) + 3 * vertex
+    }
+
+    /// Trace a subpath from one of the lights for each lane of the camera rays `ray`, splatting
+    /// each of their vertices connected to the camera.
+    #[allow(clippy::too_many_arguments)]
+    fn trace_light_subpath<'bump>(
+        &self,
+        world: &World,
+        camera: &dyn Camera,
+        samples: &WSampleProvider,
+        mut ray: WRay,
+        wavelengths: Option<Wec3>,
+        bump: &'bump Bump,
+        output_splats: &mut BumpVec<(Vec2, Srgb)>,
+    ) -> Vec<LightVertex<'bump>> {
+        let mut vertices = Vec::new();
+        if world.lights.is_empty() {
+            return vertices;
+        }
+
+        let lights = LaneLights::pick(world, samples.sample_1d(EMIT_LIGHT_1D));
+        let pick_pdf = f32x4::from(1.0 / world.lights.len() as f32);
+        let emission = lights.sample_emission(
+            &samples.sample_2d(EMIT_POINT_2D),
+            &samples.sample_2d(EMIT_DIR_2D),
+        );
+        let emission_pdf = pick_pdf * emission.pdf_point * emission.pdf_dir;
+        let mut alive = valid_lanes(&ray) & emission_pdf.cmp_gt(f32x4::ZERO);
+
+        let mut throughput =
+            spectrum_at(emission.radiance, wavelengths) * emission.cos_theta / emission_pdf;
+        // Sampling the light from the first vertex depends on where that is, so `d_vcm` is
+        // finished once it's found.
+        let mut d_vcm = mis(emission.cos_theta / emission_pdf);
+        let mut d_vc = mis(emission.cos_theta / emission_pdf);
+
+        let time = ray.time;
+        ray.origin = emission.point;
+        ray.dir = emission.dir;
+
+        for vertex in 0..self.max_vertices() {
+            if !any_lane(alive) {
+                break;
+            }
+
+            let path_length = vertex + 1;
+            let half_pixel_size_at = |t: f32x4| f32x4::from(0.0001 * 2.0 * path_length as f32) * t;
+            let (materials, point) =
+                world
+                    .hitables
+                    .closest_hits(ray, path_t_max(world, alive), &half_pixel_size_at);
+
+            let wi = -ray.dir;
+            let cos_in = point.normal.dot(wi).abs();
+            throughput *= transmission(world, point.t, wavelengths);
+            if vertex == 0 {
+                d_vcm *= mis(pick_pdf * lights.pdf(point.point, wi));
+            } else {
+                d_vcm *= mis(point.t * point.t);
+            }
+            d_vcm /= mis(cos_in);
+            d_vc /= mis(cos_in);
+
+            // Subpaths end where they leave the world or reach a surface which doesn't receive
+            // light.
+            let bsdf = LaneBSDFs::new(world, &materials, &point, bump);
+            alive = alive & bsdf.receives_light;
+            if !any_lane(alive) {
+                break;
+            }
+
+            let connection = connect_to_camera(
+                world,
+                camera,
+                &samples.sample_2d(self.light_vertex_2d(vertex)),
+                &point,
+                &bsdf,
+                wi,
+                time,
+                wavelengths,
+            );
+            let rev_pdf = bsdf.pdf(connection.dir, wi, point.normal);
+            let w_light = mis(connection.pdf) * (d_vcm + d_vc * mis(rev_pdf));
+            splat_lanes(
+                output_splats,
+                alive & connection.visible,
+                connection.uv,
+                throughput * connection.contribution / (f32x4::ONE + w_light),
+                wavelengths,
+            );
+
+            vertices.push(LightVertex {
+                point,
+                bsdf,
+                valid: alive,
+                wi,
+                throughput,
+                path_length,
+                d_vcm,
+                d_vc,
+            });
+
+            // Connecting the next vertex to the camera would make a path which is too long.
+            if path_length + 2 > self.max_path_length() {
+                break;
+            }
+
+            let [u0, v0] = samples.sample_2d(self.light_vertex_2d(vertex) + 1);
+            let [u1, v1] = samples.sample_2d(self.light_vertex_2d(vertex) + 2);
+            let se = bsdf.scatter(
+                wi,
+                &point,
+                samples.sample_1d(self.light_vertex_1d(vertex)),
+                &[u0, v0, u1, v1],
+            );
+            alive = alive & se.pdf.cmp_gt(f32x4::ZERO);
+
+            // Light leaves toward `se.wi`, which is the direction the BSDF sees it from.
+            let cos_out = point.normal.dot(se.wi).abs();
+            let rev_pdf = bsdf.pdf(se.wi, wi, point.normal);
+            d_vc = mis(cos_out / se.pdf) * (d_vc * mis(rev_pdf) + d_vcm);
+            d_vcm = mis(f32x4::ONE / se.pdf);
+            throughput *=
+                spectrum_at(bsdf.f(se.wi, wi, point.normal), wavelengths) * cos_out / se.pdf;
+
+            ray = point.create_rays(se.wi);
+        }
+
+        vertices
+    }
+
+    /// Trace a subpath from the camera for each lane, starting at the first hits `point` of the
+    /// camera rays, which are all on `material`, and return the light they gather from the lights
+    /// directly and from `light_vertices`.
+    #[allow(clippy::too_many_arguments)]
+    fn trace_camera_subpath(
+        &self,
+        world: &World,
+        camera: &dyn Camera,
+        samples: &WSampleProvider,
+        material: MaterialHandle,
+        mut point: WShadingPoint,
+        light_vertices: &[LightVertex],
+        wavelengths: Option<Wec3>,
+        bump: &Bump,
+    ) -> WSrgb {
+        let time = point.ray.time;
+        let mut materials = [Some(material); 4];
+        let mut alive = valid_lanes(&point.ray);
+        let mut radiance = WSrgb::zero();
+        let mut throughput = WSrgb::one();
+
+        // Connecting light subpaths to the camera could have found the first vertex too, with the
+        // importance of the camera ray as its pdf.
+        let (_, importance) = camera.importance(point.ray.origin, point.point, time);
+        let mut d_vcm = f32x4::merge(
+            importance.cmp_gt(f32x4::ZERO),
+            mis(f32x4::ONE / importance),
+            f32x4::ZERO,
+        );
+        let mut d_vc = f32x4::ZERO;
+
+        for vertex in 0.. {
+            let path_length = vertex + 1;
+            let wo = -point.ray.dir;
+            let cos_in = point.normal.dot(wo).abs();
+            throughput *= transmission(world, point.t, wavelengths);
+            d_vcm *= mis(point.t * point.t) / mis(cos_in);
+            d_vc /= mis(cos_in);
+
+            let bsdf = LaneBSDFs::new(world, &materials, &point, bump);
+
+            // Emissive surfaces aren't lights which can be sampled, so only camera subpaths find
+            // them, except for those inside a light, which `lights_hit_bidirectional` found as
+            // the light on the way.
+            radiance += WSrgb::merge(
+                alive,
+                throughput
+                    * spectrum_at(bsdf.le(wo, &point), wavelengths)
+                    * emission_weight(world, &point, vertex),
+                WSrgb::zero(),
+            );
+
+            // The last hit is only traced to find the lights on the way to it.
+            alive = alive & bsdf.receives_light;
+            if vertex == self.max_vertices() || !any_lane(alive) {
+                break;
+            }
+
+            if !world.lights.is_empty() {
+                let direct = self.sample_light(
+                    world,
+                    samples,
+                    vertex,
+                    &point,
+                    &bsdf,
+                    d_vcm,
+                    d_vc,
+                    wavelengths,
+                );
+                radiance += WSrgb::merge(alive, throughput * direct, WSrgb::zero());
+            }
+
+            for light_vertex in light_vertices.iter() {
+                if light_vertex.path_length + path_length + 1 > self.max_path_length() {
+                    break;
+                }
+                let connected =
+                    connect_vertices(world, &point, &bsdf, d_vcm, d_vc, light_vertex, wavelengths);
+                radiance += WSrgb::merge(
+                    alive & light_vertex.valid,
+                    throughput * connected,
+                    WSrgb::zero(),
+                );
+            }
+
+            let [u0, v0] = samples.sample_2d(self.camera_vertex_2d(vertex) + 1);
+            let [u1, v1] = samples.sample_2d(self.camera_vertex_2d(vertex) + 2);
+            let se = bsdf.scatter(
+                wo,
+                &point,
+                samples.sample_1d(self.camera_vertex_1d(vertex) + 1),
+                &[u0, v0, u1, v1],
+            );
+            alive = alive & se.pdf.cmp_gt(f32x4::ZERO);
+            if !any_lane(alive) {
+                break;
+            }
+
+            let cos_out = point.normal.dot(se.wi).abs();
+            let rev_pdf = bsdf.pdf(se.wi, wo, point.normal);
+            d_vc = mis(cos_out / se.pdf) * (d_vc * mis(rev_pdf) + d_vcm);
+            d_vcm = mis(f32x4::ONE / se.pdf);
+            throughput *= spectrum_at(se.f, wavelengths) * cos_out / se.pdf;
+
+            // Lanes which leave the world hit no material, so their subpaths end at the next
+            // vertex.
+            let ray = point.create_rays(se.wi);
+            let half_pixel_size_at = |t: f32x4| f32x4::from(0.0001 * 2.0 * path_length as f32) * t;
+            let (hit_materials, hit_point) =
+                world
+                    .hitables
+                    .closest_hits(ray, path_t_max(world, alive), &half_pixel_size_at);
+
+            radiance += WSrgb::merge(
+                alive,
+                throughput
+                    * lights_hit_bidirectional(world, &ray, hit_point.t, d_vcm, d_vc, wavelengths),
+                WSrgb::zero(),
+            );
+
+            materials = hit_materials;
+            point = hit_point;
+        }
+
+        radiance
+    }
+
+    /// Connect camera subpath vertices to points sampled on the lights.
+    #[allow(clippy::too_many_arguments)]
+    fn sample_light(
+        &self,
+        world: &World,
+        samples: &WSampleProvider,
+        vertex: usize,
+        point: &WShadingPoint,
+        bsdf: &LaneBSDFs,
+        d_vcm: f32x4,
+        d_vc: f32x4,
+        wavelengths: Option<Wec3>,
+    ) -> WSrgb {
+        let lights = LaneLights::pick(world, samples.sample_1d(self.camera_vertex_1d(vertex)));
+        let pick_pdf = f32x4::from(1.0 / world.lights.len() as f32);
+        let (end_point, le, pdf) = lights.sample(
+            &samples.sample_2d(self.camera_vertex_2d(vertex)),
+            point.point,
+        );
+
+        let wo = -point.ray.dir;
+        let wi = end_point - point.point;
+        let dist = wi.mag();
+        let wi = wi / dist;
+        let cos = point.normal.dot(wi).max(f32x4::ZERO);
+
+        let (pdf_point, pdf_dir, cos_light) = lights.emission_pdf(end_point, -wi);
+        let bsdf_pdf = bsdf.pdf(wo, wi, point.normal);
+        let rev_pdf = bsdf.pdf(wi, wo, point.normal);
+        let w_light = mis(bsdf_pdf / (pick_pdf * pdf));
+        let w_camera =
+            mis(pdf_point * pdf_dir * cos / (pdf * cos_light)) * (d_vcm + d_vc * mis(rev_pdf));
+
+        let occluded =
+            world
+                .hitables
+                .test_occluded(offset_point(point, wi), end_point, point.ray.time);
+        let f = spectrum_at(bsdf.f(wo, wi, point.normal), wavelengths);
+
+        WSrgb::merge(
+            pdf.cmp_gt(f32x4::ZERO) & cos_light.cmp_gt(f32x4::ZERO),
+            spectrum_at(le, wavelengths)
+                * f
+                * cos
+                * transmission(world, dist, wavelengths)
+                * occluded
+                / (pick_pdf * pdf * (w_light + f32x4::ONE + w_camera)),
+            WSrgb::zero(),
+        )
+    }
+}
+
+/// Connect camera subpath vertices to light subpath vertices.
+fn connect_vertices(
+    world: &World,
+    point: &WShadingPoint,
+    bsdf: &LaneBSDFs,
+    d_vcm: f32x4,
+    d_vc: f32x4,
+    light_vertex: &LightVertex,
+    wavelengths: Option<Wec3>,
+) -> WSrgb {
+    let wo = -point.ray.dir;
+    let light_point = &light_vertex.point;
+    let dir = light_point.point - point.point;
+    let dist_sq = dir.mag_sq();
+    let dist = dist_sq.sqrt();
+    let dir = dir / dist;
+
+    let cos_camera = point.normal.dot(dir).max(f32x4::ZERO);
+    let cos_light = light_point.normal.dot(-dir).max(f32x4::ZERO);
+
+    let camera_f = spectrum_at(bsdf.f(wo, dir, point.normal), wavelengths);
+    let camera_pdf = bsdf.pdf(wo, dir, point.normal);
+    let camera_rev_pdf = bsdf.pdf(dir, wo, point.normal);
+
+    let light_bsdf = &light_vertex.bsdf;
+    let light_f = spectrum_at(
+        light_bsdf.f(-dir, light_vertex.wi, light_point.normal),
+        wavelengths,
+    );
+    let light_pdf = light_bsdf.pdf(light_vertex.wi, -dir, light_point.normal);
+    let light_rev_pdf = light_bsdf.pdf(-dir, light_vertex.wi, light_point.normal);
+
+    // Each side's pdf of sampling the other's vertex is converted to be with respect to area.
+    let w_light = mis(camera_pdf * cos_light / dist_sq)
+        * (light_vertex.d_vcm + light_vertex.d_vc * mis(light_rev_pdf));
+    let w_camera = mis(light_pdf * cos_camera / dist_sq) * (d_vcm + d_vc * mis(camera_rev_pdf));
+
+    let occluded = world.hitables.test_occluded(
+        offset_point(point, dir),
+        offset_point(light_point, -dir),
+        point.ray.time,
+    );
+    let geometry = cos_camera * cos_light / dist_sq;
+
+    WSrgb::merge(
+        dist_sq.cmp_gt(f32x4::ZERO),
+        camera_f
+            * light_f
+            * light_vertex.throughput
+            * geometry
+            * transmission(world, dist, wavelengths)
+            * occluded
+            / (w_light + f32x4::ONE + w_camera),
+        WSrgb::zero(),
+    )
+}
+
+/// The light from lights which `ray`, scattered from a camera subpath vertex, passes through
+/// before reaching `t`, weighted against finding the same paths with the other strategies.
+/// `d_vcm` and `d_vc` are those of the vertex the ray was scattered from.
+fn lights_hit_bidirectional(
+    world: &World,
+    ray: &WRay,
+    t: f32x4,
+    d_vcm: f32x4,
+    d_vc: f32x4,
+    wavelengths: Option<Wec3>,
+) -> WSrgb {
+    let pick_pdf = f32x4::from(1.0 / world.lights.len() as f32);
+
+    let mut radiance = WSrgb::zero();
+    for light in world.lights.iter() {
+        let (dist, le) = light.intersect(ray.origin, ray.dir);
+        let point = ray.origin + ray.dir * dist.min(t);
+
+        let direct_pdf = pick_pdf * light.pdf(ray.origin, ray.dir);
+        let (pdf_point, pdf_dir, cos_light) = light.emission_pdf(point, -ray.dir);
+        let w_camera =
+            mis(direct_pdf) * d_vcm + mis(pick_pdf * pdf_point * pdf_dir / cos_light) * d_vc;
+
+        radiance += WSrgb::merge(
+            dist.cmp_lt(t) & cos_light.cmp_gt(f32x4::ZERO),
+            spectrum_at(le, wavelengths) * transmission(world, dist, wavelengths)
+                / (f32x4::ONE + w_camera),
+            WSrgb::zero(),
+        );
+    }
+    radiance
+}
+
+impl Integrator for BidirectionalIntegrator {
+    /// Whole paths are traced from the first hits of camera rays.
+    fn max_depth(&self) -> usize {
+        0
+    }
+
+    fn requested_1d_sample_sets(&self, _depth: usize) -> usize {
+        self.light_vertex_1d(self.max_vertices())
+    }
+
+    fn requested_2d_sample_sets(&self, _depth: usize) -> usize {
+        self.light_vertex_2d(self.max_vertices())
+    }
+
+    fn integrate(
+        &self,
+        world: &World,
+        samples: &WSampleProvider,
+        _depth: usize,
+        material: MaterialHandle,
+        intersection: WShadingPoint,
+        bump: &Bump,
+        _spawned_rays: &mut BumpVec<Ray>,
+        output_samples: &mut BumpVec<(Vec2u, ChannelSample)>,
+        output_splats: &mut BumpVec<(Vec2, Srgb)>,
+    ) {
+        let wo = -intersection.ray.dir;
+        let bsdf = world
+            .materials
+            .get(material)
+            .get_bsdf_at(&intersection, bump);
+        record_first_hit(
+            &intersection,
+            material.0 as f32,
+            bsdf.albedo(wo, &intersection),
+            output_samples,
+        );
+        if bsdf.receives_light() {
+            record_first_surface_hit(&intersection, output_samples);
+        }
+
+        let camera = world.cameras.get(self.camera);
+        let wavelengths = if self.spectral {
+            Some(intersection.ray.wavelengths)
+        } else {
+            None
+        };
+
+        let light_vertices = self.trace_light_subpath(
+            world,
+            camera,
+            samples,
+            intersection.ray,
+            wavelengths,
+            bump,
+            output_splats,
+        );
+        let radiance = self.trace_camera_subpath(
+            world,
+            camera,
+            samples,
+            material,
+            intersection,
+            &light_vertices,
+            wavelengths,
+            bump,
+        );
+
+        let rays: [Ray; 4] = intersection.ray.into();
+        let radiances = lanes_rgb(radiance, wavelengths);
+        for (ray, radiance) in rays.iter().zip(radiances.iter()) {
+            if ray.valid {
+                let sample = if bsdf.receives_light() {
+                    ChannelSample::Color(*radiance)
+                } else {
+                    ChannelSample::Background(*radiance)
+                };
+                output_samples.push((ray.tile_coord, sample));
+            }
+        }
+    }
+}
+
+/// A light tracer, which only traces paths from the lights and connects each of their vertices
+/// to the camera, splatting them into whichever pixel sees them. It finds caustics, light focused
+/// by mirrors and glass onto diffuse surfaces, which paths from the camera hardly ever do, but
+/// can't see anything in mirrors and glass, which only send light on in a single direction. Camera
+/// rays only see emissive surfaces and the background directly.
+///
+/// Each camera sample traces one path from a light per lane at the first hit of the camera ray,
+/// the lanes together like the `BidirectionalIntegrator`'s subpaths. Volumes only absorb light,
+/// nothing scatters in them.
+#[derive(Clone, Copy)]
+pub struct LightTracingIntegrator {
+    /// Paths from the lights bounce off as many surfaces as paths from the camera of the
+    /// `PathTracingIntegrator` with the same `max_bounces` do.
+    pub max_bounces: usize,
+    /// The camera the film is rendered with, which paths are connected to.
+    pub camera: CameraHandle,
+    /// Trace wavelengths instead of RGB, see `PathTracingIntegrator::spectral`.
+    pub spectral: bool,
+}
+
+// Sample sets used by the `LightTracingIntegrator`, which traces whole paths at depth 0. The
+// first are the same as the `BidirectionalIntegrator`'s, to leave a light, then there's one 1d set
+// for each vertex, to pick a lobe, and three 2d sets, one to pick a point on the lens and two to
+// scatter.
+impl LightTracingIntegrator {
+    /// The most vertices a path has, not counting the ones on the camera and the light.
+    fn max_vertices(&self) -> usize {
+        self.max_bounces + 1
+    }
+
+    fn vertex_1d(&self, vertex: usize) -> usize {
+        EMIT_LIGHT_1D + 1 + vertex
+    }
+
+    fn vertex_2d(&self, vertex: usize) -> usize {
+        EMIT_DIR_2D + 1 + 3 * vertex
+    }
+
+    /// Trace a path from one of the lights for each lane of the camera rays `ray`, splatting each
+    /// of their vertices connected to the camera.
+    #[allow(clippy::too_many_arguments)]
+    fn trace_light_path(
+        &self,
+        world: &World,
+        camera: &dyn Camera,
+        samples: &WSampleProvider,
+        mut ray: WRay,
+        wavelengths: Option<Wec3>,
+        bump: &Bump,
+        output_splats: &mut BumpVec<(Vec2, Srgb)>,
+    ) {
+        if world.lights.is_empty() {
+            return;
+        }
+
+        let lights = LaneLights::pick(world, samples.sample_1d(EMIT_LIGHT_1D));
+        let emission = lights.sample_emission(
+            &samples.sample_2d(EMIT_POINT_2D),
+            &samples.sample_2d(EMIT_DIR_2D),
+        );
+        let emission_pdf =
+            emission.pdf_point * emission.pdf_dir / f32x4::from(world.lights.len() as f32);
+        let mut alive = valid_lanes(&ray) & emission_pdf.cmp_gt(f32x4::ZERO);
+
+        let mut throughput =
+            spectrum_at(emission.radiance, wavelengths) * emission.cos_theta / emission_pdf;
+
+        let time = ray.time;
+        ray.origin = emission.point;
+        ray.dir = emission.dir;
+
+        for vertex in 0..self.max_vertices() {
+            if !any_lane(alive) {
+                break;
+            }
+
+            let path_length = vertex + 1;
+            let half_pixel_size_at = |t: f32x4| f32x4::from(0.0001 * 2.0 * path_length as f32) * t;
+            let (materials, point) =
+                world
+                    .hitables
+                    .closest_hits(ray, path_t_max(world, alive), &half_pixel_size_at);
+
+            let wi = -ray.dir;
+            throughput *= transmission(world, point.t, wavelengths);
+
+            let bsdf = LaneBSDFs::new(world, &materials, &point, bump);
+            alive = alive & bsdf.receives_light;
+            if !any_lane(alive) {
+                break;
+            }
+
+            let connection = connect_to_camera(
+                world,
+                camera,
+                &samples.sample_2d(self.vertex_2d(vertex)),
+                &point,
+                &bsdf,
+                wi,
+                time,
+                wavelengths,
+            );
+            splat_lanes(
+                output_splats,
+                alive & connection.visible,
+                connection.uv,
+                throughput * connection.contribution,
+                wavelengths,
+            );
+
+            if vertex + 1 == self.max_vertices() {
+                break;
+            }
+
+            let [u0, v0] = samples.sample_2d(self.vertex_2d(vertex) + 1);
+            let [u1, v1] = samples.sample_2d(self.vertex_2d(vertex) + 2);
+            let se = bsdf.scatter(
+                wi,
+                &point,
+                samples.sample_1d(self.vertex_1d(vertex)),
+                &[u0, v0, u1, v1],
+            );
+            alive = alive & se.pdf.cmp_gt(f32x4::ZERO);
+
+            // Light leaves toward `se.wi`, which is the direction the BSDF sees it from.
+            let cos_out = point.normal.dot(se.wi).abs();
+            throughput *=
+                spectrum_at(bsdf.f(se.wi, wi, point.normal), wavelengths) * cos_out / se.pdf;
+
+            ray = point.create_rays(se.wi);
+        }
+    }
+}
+
+impl Integrator for LightTracingIntegrator {
+    /// Whole paths are traced from the lights at the first hits of camera rays.
+    fn max_depth(&self) -> usize {
+        0
+    }
+
+    fn requested_1d_sample_sets(&self, _depth: usize) -> usize {
+        self.vertex_1d(self.max_vertices())
+    }
+
+    fn requested_2d_sample_sets(&self, _depth: usize) -> usize {
+        self.vertex_2d(self.max_vertices())
+    }
+
+    fn integrate(
+        &self,
+        world: &World,
+        samples: &WSampleProvider,
+        _depth: usize,
+        material: MaterialHandle,
+        intersection: WShadingPoint,
+        bump: &Bump,
+        _spawned_rays: &mut BumpVec<Ray>,
+        output_samples: &mut BumpVec<(Vec2u, ChannelSample)>,
+        output_splats: &mut BumpVec<(Vec2, Srgb)>,
+    ) {
+        let wo = -intersection.ray.dir;
+        let bsdf = world
+            .materials
+            .get(material)
+            .get_bsdf_at(&intersection, bump);
+        record_first_hit(
+            &intersection,
+            material.0 as f32,
+            bsdf.albedo(wo, &intersection),
+            output_samples,
+        );
+        if bsdf.receives_light() {
+            record_first_surface_hit(&intersection, output_samples);
+        }
+
+        let camera = world.cameras.get(self.camera);
+        let wavelengths = if self.spectral {
+            Some(intersection.ray.wavelengths)
+        } else {
+            None
+        };
+
+        self.trace_light_path(
+            world,
+            camera,
+            samples,
+            intersection.ray,
+            wavelengths,
+            bump,
+            output_splats,
+        );
+
+        // The camera rays only see what the surface they hit emits.
+        let le = spectrum_at(bsdf.le(wo, &intersection), wavelengths)
+            * transmission(world, intersection.t, wavelengths);
+
+        let rays: [Ray; 4] = intersection.ray.into();
+        let radiances = lanes_rgb(le, wavelengths);
+        for (ray, radiance) in rays.iter().zip(radiances.iter()) {
+            if ray.valid {
+                let sample = if bsdf.receives_light() {
+                    ChannelSample::Color(*radiance)
+                } else {
+                    ChannelSample::Background(*radiance)
+                };
+                output_samples.push((ray.tile_coord, sample));
+            }
+        }
+    }
+}
+
+/// Shades surfaces by how much of the hemisphere above them is open within `radius`, as if they
+/// were white and lit evenly from every direction, for quickly judging the shape of a scene
+/// without setting up lights or materials. The result is written to the Color channel, and
+/// surfaces which don't receive light, like the sky, are white.
+#[derive(Clone, Copy)]
+pub struct AmbientOcclusionIntegrator {
+    /// The number of occlusion rays traced from each camera ray's first hit.
+    pub samples: usize,
+    /// Geometry further away than this doesn't occlude.
+    pub radius: f32,
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn max_depth(&self) -> usize {
+        0
+    }
+
+    fn requested_1d_sample_sets(&self, _depth: usize) -> usize {
+        0
+    }
+
+    fn requested_2d_sample_sets(&self, _depth: usize) -> usize {
+        self.samples
+    }
+
+    fn integrate(
+        &self,
+        world: &World,
+        samples: &WSampleProvider,
+        _depth: usize,
+        material: MaterialHandle,
+        intersection: WShadingPoint,
+        bump: &Bump,
+        _spawned_rays: &mut BumpVec<Ray>,
+        output_samples: &mut BumpVec<(Vec2u, ChannelSample)>,
+        _output_splats: &mut BumpVec<(Vec2, Srgb)>,
+    ) {
+        let receives_light = world
+            .materials
+            .get(material)
+            .get_bsdf_at(&intersection, bump)
+            .receives_light();
+        record_first_hit(
+            &intersection,
+            material.0 as f32,
+            WSrgb::one(),
+            output_samples,
+        );
+
+        let rays: [Ray; 4] = intersection.ray.into();
+        if !receives_light {
+            for ray in rays.iter().filter(|ray| ray.valid) {
+                output_samples.push((ray.tile_coord, ChannelSample::Background(Srgb::one())));
+            }
+            return;
+        }
+        record_first_surface_hit(&intersection, output_samples);
+
+        // Occlusion rays leave the side of the surface the camera sees.
+        let wo = -intersection.ray.dir;
+        let normal = intersection.normal * intersection.normal.dot(wo).signum();
+        let basis = normal.get_orthonormal_basis();
+        let origin = intersection.point + normal * intersection.offset_by;
+        let radius = f32x4::from(self.radius);
+
+        // With cosine weighted directions, the fraction of unoccluded rays estimates the cosine
+        // weighted fraction of the hemisphere which is open.
+        let mut open = f32x4::ZERO;
+        for i in 0..self.samples {
+            let dir = basis * Wec3::cosine_weighted_in_hemisphere(&samples.sample_2d(i));
+            open +=
+                world
+                    .hitables
+                    .test_occluded(origin, origin + dir * radius, intersection.ray.time);
+        }
+        let open = open / f32x4::from(self.samples.max(1) as f32);
+
+        for (ray, open) in rays.iter().zip(open.as_ref().iter()) {
+            if ray.valid {
+                let color = Srgb::new(*open, *open, *open);
+                output_samples.push((ray.tile_coord, ChannelSample::Color(color)));
+            }
+        }
+    }
+}
+
+/// What the `DebugIntegrator` shows in the Color channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    /// The number of steps taken to find the first hit of each camera ray, summed over all the
+    /// hitables searched, as a heatmap from blue for none to red for `sdf::MAX_MARCHES` or more.
+    MarchSteps,
+    /// How the search for the first hit of each camera ray ended: white where it hit a surface
+    /// which receives light, black where it only found the background, red where it ran out of
+    /// marches and took wherever it got to as the hit, and magenta where it went NaN.
+    HitMask,
+    /// How far from the surface the search for the first hit of each camera ray stopped, as a
+    /// heatmap from blue for `1e-6` or less to red for 1 or more, on a log scale.
+    TerminationDistance,
+    /// The normal at the first hit of each camera ray in camera space, with x to the right, y up
+    /// and z toward the camera, mapped from `[-1, 1]` to `[0, 1]`.
+    CameraNormals,
+    /// The number of bounces paths of the `PathTracingIntegrator` made before they ended, as a
+    /// heatmap from blue for none to red for `max_bounces`.
+    Bounces,
+    /// Where the samples of the `PathTracingIntegrator` are NaN, in red, or infinite, in yellow.
+    NonFinite,
+}
+
+impl DebugView {
+    pub const NAMES: &'static [&'static str] = &[
+        "steps",
+        "hit-mask",
+        "termination-distance",
+        "normals",
+        "bounces",
+        "non-finite",
+    ];
+
+    /// Looks up a debug view by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "steps" => Some(DebugView::MarchSteps),
+            "hit-mask" => Some(DebugView::HitMask),
+            "termination-distance" => Some(DebugView::TerminationDistance),
+            "normals" => Some(DebugView::CameraNormals),
+            "bounces" => Some(DebugView::Bounces),
+            "non-finite" => Some(DebugView::NonFinite),
+            _ => None,
+        }
+    }
+
+    /// Whether the view shows how the paths of the `PathTracingIntegrator` went, rather than how
+    /// camera rays were traced.
+    fn traces_paths(self) -> bool {
+        matches!(self, DebugView::Bounces | DebugView::NonFinite)
+    }
+}
+
+/// Renders a `DebugView` of the scene, for seeing where tracing it goes wrong or gets slow, like
+/// where the SDF tracer runs out of marches or its hit threshold is too coarse.
+///
+/// The views of how camera rays were traced trace them again with `HitableStore::trace_stats`.
+/// The views of how paths went render with `path_tracer`, and replace the color of the samples
+/// it records.
+#[derive(Clone, Copy)]
+pub struct DebugIntegrator {
+    pub view: DebugView,
+    /// The camera the film is rendered with, for tracing camera rays the same way the film does.
+    pub camera: CameraHandle,
+    pub path_tracer: PathTracingIntegrator,
+}
+
+/// A heatmap color for `x` in `[0, 1]`, going from blue through cyan, green and yellow to red.
+fn heatmap(x: f32) -> Srgb {
+    const STOPS: [(f32, f32, f32); 5] = [
+        (0.0, 0.0, 1.0),
+        (0.0, 1.0, 1.0),
+        (0.0, 1.0, 0.0),
+        (1.0, 1.0, 0.0),
+        (1.0, 0.0, 0.0),
+    ];
+    // NaN ends up at the bottom of the scale.
+    let x = x.max(0.0).min(1.0) * (STOPS.len() - 1) as f32;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as f32;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    Srgb::new(
+        a.0 + (b.0 - a.0) * f,
+        a.1 + (b.1 - a.1) * f,
+        a.2 + (b.2 - a.2) * f,
+    )
+}
+
+impl DebugIntegrator {
+    /// The colors of the views of how camera rays were traced to `intersection`.
+    fn trace_colors(
+        &self,
+        world: &World,
+        intersection: &WShadingPoint,
+        receives_light: bool,
+    ) -> [Srgb; 4] {
+        let camera = world.cameras.get(self.camera);
+        let half_pixel_size_at = |t: f32x4| camera.half_pixel_size_at(t);
+        let stats = world.hitables.trace_stats(
+            intersection.ray,
+            f32x4::from(world.radius * 2.0),
+            &half_pixel_size_at,
+        );
+
+        let mut colors = [Srgb::zero(); 4];
+        match self.view {
+            DebugView::MarchSteps => {
+                for (color, steps) in colors.iter_mut().zip(stats.steps.as_ref().iter()) {
+                    *color = heatmap(*steps / crate::sdf::MAX_MARCHES as f32);
+                }
+            }
+            DebugView::HitMask => {
+                let hit = if receives_light {
+                    Srgb::one()
+                } else {
+                    Srgb::zero()
+                };
+                let exhausted = stats.exhausted.move_mask();
+                let nan = stats.nan.move_mask();
+                for (lane, color) in colors.iter_mut().enumerate() {
+                    *color = if nan & (1 << lane) != 0 {
+                        Srgb::new(1.0, 0.0, 1.0)
+                    } else if exhausted & (1 << lane) != 0 {
+                        Srgb::new(1.0, 0.0, 0.0)
+                    } else {
+                        hit
+                    };
+                }
+            }
+            DebugView::TerminationDistance => {
+                for (color, dist) in colors.iter_mut().zip(stats.surface_dist.as_ref().iter()) {
+                    *color = heatmap((dist.log10() + 6.0) / 6.0);
+                }
+            }
+            DebugView::CameraNormals => {
+                let basis = camera.basis(intersection.ray.time);
+                let normal = intersection.normal;
+                let half = f32x4::from(0.5);
+                let normal = WSrgb::new(
+                    normal.dot(basis.cols[0]) * half + half,
+                    normal.dot(basis.cols[1]) * half + half,
+                    normal.dot(basis.cols[2]) * half + half,
+                );
+                colors = normal.into();
+            }
+            DebugView::Bounces | DebugView::NonFinite => unreachable!(),
+        }
+        colors
+    }
+
+    /// The color of the view of how a path went, which ended at `depth` with `radiance`.
+    fn path_color(&self, depth: usize, radiance: Srgb) -> Srgb {
+        match self.view {
+            DebugView::Bounces => {
+                heatmap(depth as f32 / self.path_tracer.max_bounces.max(1) as f32)
+            }
+            DebugView::NonFinite => {
+                if radiance.is_nan() {
+                    Srgb::new(1.0, 0.0, 0.0)
+                } else if radiance.x.is_infinite()
+                    || radiance.y.is_infinite()
+                    || radiance.z.is_infinite()
+                {
+                    Srgb::new(1.0, 1.0, 0.0)
+                } else {
+                    Srgb::zero()
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn max_depth(&self) -> usize {
+        if self.view.traces_paths() {
+            self.path_tracer.max_depth()
+        } else {
+            0
+        }
+    }
+
+    fn requested_1d_sample_sets(&self, depth: usize) -> usize {
+        if self.view.traces_paths() {
+            self.path_tracer.requested_1d_sample_sets(depth)
+        } else {
+            0
+        }
+    }
+
+    fn requested_2d_sample_sets(&self, depth: usize) -> usize {
+        if self.view.traces_paths() {
+            self.path_tracer.requested_2d_sample_sets(depth)
+        } else {
+            0
+        }
+    }
+
+    fn integrate(
+        &self,
+        world: &World,
+        samples: &WSampleProvider,
+        depth: usize,
+        material: MaterialHandle,
+        intersection: WShadingPoint,
+        bump: &Bump,
+        spawned_rays: &mut BumpVec<Ray>,
+        output_samples: &mut BumpVec<(Vec2u, ChannelSample)>,
+        output_splats: &mut BumpVec<(Vec2, Srgb)>,
+    ) {
+        if self.view.traces_paths() {
+            let first_sample = output_samples.len();
+            self.path_tracer.integrate(
+                world,
+                samples,
+                depth,
+                material,
+                intersection,
+                bump,
+                spawned_rays,
+                output_samples,
+                output_splats,
+            );
+            for (_, sample) in output_samples[first_sample..].iter_mut() {
+                if let ChannelSample::Color(radiance) | ChannelSample::Background(radiance) = sample
+                {
+                    *radiance = self.path_color(depth, *radiance);
+                }
+            }
+            return;
+        }
+
+        let wo = -intersection.ray.dir;
+        let bsdf = world
+            .materials
+            .get(material)
+            .get_bsdf_at(&intersection, bump);
+        record_first_hit(
+            &intersection,
+            material.0 as f32,
+            bsdf.albedo(wo, &intersection),
+            output_samples,
+        );
+        let receives_light = bsdf.receives_light();
+        if receives_light {
+            record_first_surface_hit(&intersection, output_samples);
+        }
+
+        let colors = self.trace_colors(world, &intersection, receives_light);
+        let rays: [Ray; 4] = intersection.ray.into();
+        for (ray, color) in rays.iter().zip(colors.iter()) {
+            if ray.valid {
+                output_samples.push((ray.tile_coord, ChannelSample::Color(*color)));
+            }
+        }
+    }
+}
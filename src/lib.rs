@@ -0,0 +1,87 @@
+//! A CPU-based path tracing renderer focused on rendering SDFs, specifically fractals.
+//!
+//! A render is made by building a [`World`] (either by hand or by loading a scene file with
+//! [`scene::load`]), creating a [`Film`] with the channels you want to record, and then calling
+//! [`Film::render_frame_into`] with an [`Integrator`] and a reconstruction [`Filter`].
+//!
+//! ```no_run
+//! use rayn::typenum::U2;
+//! use rayn::{
+//!     BlackmanHarrisFilter, ChannelKind, DisplayTransform, Film, OutputFormat, PathTracingIntegrator,
+//! };
+//!
+//! let scene = rayn::scene::load("scenes/mandelbox.ron").unwrap();
+//!
+//! let mut film =
+//!     Film::<U2>::new(&[ChannelKind::Color, ChannelKind::Background], scene.settings.resolution())
+//!         .unwrap();
+//!
+//! let integrator = PathTracingIntegrator {
+//!     max_bounces: scene.settings.max_bounces,
+//!     volume_marches: 2,
+//!     spectral: false,
+//! };
+//!
+//! film.render_frame_into(
+//!     &scene.world,
+//!     scene.camera,
+//!     &integrator,
+//!     &BlackmanHarrisFilter::default(),
+//!     rayn::math::Extent2u::new(16, 16),
+//!     0,
+//!     0.0..1.0 / 24.0,
+//!     scene.settings.samples,
+//!     None,
+//! );
+//!
+//! film.save_to(
+//!     &[(ChannelKind::Color, OutputFormat::Png), (ChannelKind::Color, OutputFormat::Hdr)],
+//!     "renders",
+//!     "example",
+//!     false,
+//!     DisplayTransform::default(),
+//!     None,
+//! )
+//! .unwrap();
+//! ```
+
+pub mod animation;
+pub mod camera;
+pub mod color;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod hitable;
+pub mod integrator;
+pub mod light;
+pub mod material;
+pub mod math;
+pub mod postprocess;
+pub mod ray;
+pub mod sampler;
+pub mod scene;
+pub mod sdf;
+pub mod spectrum;
+pub mod sphere;
+pub mod tonemap;
+pub mod volume;
+pub mod world;
+
+pub use generic_array::typenum;
+
+pub use camera::{Camera, CameraHandle, CameraStore};
+pub use color::{ColorSpace, OutputTransform};
+pub use film::{AdaptiveSampling, ChannelKind, CropWindow, Film, OutputFormat, SampleBudget};
+pub use filter::{
+    BlackmanHarrisFilter, BoxFilter, Filter, LanczosSincFilter, MitchellNetravaliFilter,
+};
+pub use hitable::{Hitable, HitableStore};
+pub use integrator::{
+    AmbientOcclusionIntegrator, BidirectionalIntegrator, DebugIntegrator, DebugView, Integrator,
+    LightTracingIntegrator, PathTracingIntegrator,
+};
+pub use light::Light;
+pub use material::{Material, MaterialHandle, MaterialStore, BSDF};
+pub use scene::{RenderSettings, Scene};
+pub use tonemap::{DisplayTransform, ToneMapOperator};
+pub use world::World;
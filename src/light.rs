@@ -1,4 +1,4 @@
-use crate::math::{f32x4, OrthonormalBasis, Vec3, Wec3};
+use crate::math::{f32x4, OrthonormalBasis, RandomSample3d, Vec3, Wec3};
 use crate::spectrum::{Srgb, WSrgb};
 use sdfu::mathtypes::Lerp;
 
@@ -6,6 +6,27 @@ pub trait Light: Send + Sync {
     /// returns (sampled point, output radiance toward ref, pdf of sample wrt solid angle wrt ref point)
     fn sample(&self, samples: &[f32x4; 2], point: Wec3) -> (Wec3, WSrgb, f32x4);
 
+    /// The pdf with respect to solid angle of `sample` choosing direction `wi` from `point`, or
+    /// zero if `wi` misses the light.
+    fn pdf(&self, point: Wec3, wi: Wec3) -> f32x4;
+
+    /// returns (distance along the ray from `point` in direction `wi` to the light, radiance
+    /// toward `point`). The distance is infinite where the ray misses the light.
+    fn intersect(&self, point: Wec3, wi: Wec3) -> (f32x4, WSrgb);
+
+    /// A mask of whether `point` is inside the light, like the emissive geometry which usually
+    /// stands in for the light where it's seen directly is.
+    fn contains(&self, point: Wec3) -> f32x4;
+
+    /// Samples a ray leaving the light, for tracing light from it into the scene rather than
+    /// toward a point.
+    fn sample_emission(&self, point_samples: &[f32x4; 2], dir_samples: &[f32x4; 2]) -> WEmission;
+
+    /// returns (pdf of `sample_emission` choosing `point` on the light wrt area, pdf of it choosing
+    /// direction `dir` from there wrt solid angle, cosine between `dir` and the light's normal at
+    /// `point`)
+    fn emission_pdf(&self, point: Wec3, dir: Wec3) -> (f32x4, f32x4, f32x4);
+
     /// returns (distance along ray of sampled point, pdf of sample)
     fn sample_volume_scattering(
         &self,
@@ -16,6 +37,20 @@ pub trait Light: Send + Sync {
     ) -> (f32x4, f32x4);
 }
 
+/// A ray leaving a light, see `Light::sample_emission`.
+pub struct WEmission {
+    pub point: Wec3,
+    pub dir: Wec3,
+    /// The radiance leaving `point` in direction `dir`.
+    pub radiance: WSrgb,
+    /// The pdf of choosing `point` with respect to area.
+    pub pdf_point: f32x4,
+    /// The pdf of choosing `dir` with respect to solid angle.
+    pub pdf_dir: f32x4,
+    /// The cosine between `dir` and the light's normal at `point`.
+    pub cos_theta: f32x4,
+}
+
 #[derive(Clone, Copy)]
 pub struct SphereLight {
     pos: Wec3,
@@ -31,6 +66,11 @@ impl SphereLight {
             rad: f32x4::from(rad),
         }
     }
+
+    /// The pdf of a point picked uniformly on the sphere, with respect to area.
+    fn area_pdf(&self) -> f32x4 {
+        f32x4::ONE / (f32x4::from(4.0) * f32x4::PI * self.rad * self.rad)
+    }
 }
 
 impl Light for SphereLight {
@@ -71,6 +111,64 @@ impl Light for SphereLight {
         (point, self.emission, pdf)
     }
 
+    fn pdf(&self, p: Wec3, wi: Wec3) -> f32x4 {
+        // `sample` picks directions uniformly from the cone the sphere subtends.
+        let dir_to_light = self.pos - p;
+        let dist_to_light_sq = dir_to_light.mag_sq();
+        let cos_theta = wi.dot(dir_to_light) / dist_to_light_sq.sqrt();
+
+        let sin_theta_max_2 = self.rad * self.rad / dist_to_light_sq;
+        let cos_theta_max = f32x4::ZERO.max(f32x4::ONE - sin_theta_max_2).sqrt();
+
+        f32x4::merge(
+            cos_theta.cmp_ge(cos_theta_max),
+            uniform_cone_pdf(cos_theta_max),
+            f32x4::ZERO,
+        )
+    }
+
+    fn intersect(&self, p: Wec3, wi: Wec3) -> (f32x4, WSrgb) {
+        let oc = p - self.pos;
+        let b = oc.dot(wi);
+        let c = oc.mag_sq() - self.rad * self.rad;
+        let descrim = b * b - c;
+
+        // The near side of the sphere, which is the side `sample` picks points on.
+        let t = -b - descrim.max(f32x4::ZERO).sqrt();
+        let hit = descrim.cmp_gt(f32x4::ZERO) & t.cmp_gt(f32x4::ZERO);
+
+        (
+            f32x4::merge(hit, t, f32x4::from(std::f32::INFINITY)),
+            self.emission,
+        )
+    }
+
+    fn contains(&self, p: Wec3) -> f32x4 {
+        (p - self.pos).mag_sq().cmp_le(self.rad * self.rad)
+    }
+
+    /// Picks a point uniformly on the sphere and a cosine weighted direction around its normal.
+    fn sample_emission(&self, point_samples: &[f32x4; 2], dir_samples: &[f32x4; 2]) -> WEmission {
+        let normal = Wec3::rand_on_unit_sphere(point_samples);
+        let local_dir = Wec3::cosine_weighted_in_hemisphere(dir_samples);
+        let dir = (normal.get_orthonormal_basis() * local_dir).normalized();
+
+        WEmission {
+            point: self.pos + normal * self.rad,
+            dir,
+            radiance: self.emission,
+            pdf_point: self.area_pdf(),
+            pdf_dir: local_dir.z / f32x4::PI,
+            cos_theta: local_dir.z,
+        }
+    }
+
+    fn emission_pdf(&self, point: Wec3, dir: Wec3) -> (f32x4, f32x4, f32x4) {
+        let normal = (point - self.pos).normalized();
+        let cos_theta = normal.dot(dir).max(f32x4::ZERO);
+        (self.area_pdf(), cos_theta / f32x4::PI, cos_theta)
+    }
+
     /// returns (radiance toward sampled point, distance along ray of sampled point, pdf of sample)
     fn sample_volume_scattering(
         &self,
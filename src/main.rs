@@ -1,76 +1,359 @@
-use generic_array::typenum::*;
-
-mod animation;
-mod camera;
-mod film;
-mod filter;
-mod hitable;
-mod integrator;
-mod light;
-mod material;
-mod math;
-mod ray;
-mod sampler;
-mod sdf;
-mod spectrum;
-mod sphere;
-mod volume;
-mod world;
+mod cli;
 mod setup;
 
-use film::{ChannelKind, Film};
-use filter::BlackmanHarrisFilter;
-use integrator::PathTracingIntegrator;
-use math::Extent2u;
+use cli::{FilterKind, IntegratorKind, Options};
 
-use std::time::Instant;
+use generic_array::ArrayLength;
+
+use rayn::film::{AdaptiveSampling, ChannelStorage, ChannelTileStorage, SampleBudget};
+use rayn::postprocess::PostEffect;
+use rayn::scene::{self, RenderSettings, Scene};
+use rayn::typenum::*;
+use rayn::{
+    AmbientOcclusionIntegrator, BidirectionalIntegrator, BlackmanHarrisFilter, BoxFilter,
+    ChannelKind, DebugIntegrator, Film, Filter, Integrator, LanczosSincFilter,
+    LightTracingIntegrator, MitchellNetravaliFilter, PathTracingIntegrator,
+};
+
+use std::time::{Duration, Instant};
 
 fn main() {
+    let options = Options::from_args();
+
     rayon::ThreadPoolBuilder::new()
-        .num_threads(num_cpus::get())
+        .num_threads(options.threads)
         .build_global()
         .unwrap();
 
-    let (camera, world) = setup::setup();
-
-    let mut film = Film::<U4>::new(
-        &[
-            ChannelKind::Color,
-            ChannelKind::Alpha,
-            ChannelKind::Background,
-            ChannelKind::WorldNormal,
-        ],
-        crate::setup::RESOLUTION
-    )
-    .unwrap();
-
-    let frame_rate = 24;
-    let frame_range = 1..2;
-    let shutter_speed = 1.0 / 24.0;
-
-    let filter = BlackmanHarrisFilter::new(1.5);
-    // let filter = BoxFilter::default();
-    let integrator = PathTracingIntegrator {
-        max_bounces: crate::setup::MAX_INDIRECT_BOUNCES,
-        volume_marches: crate::setup::VOLUME_MARCHES_PER_SAMPLE,
+    // Render the given scene file, or the scene built in `setup.rs` if there isn't one.
+    let scene = match options.scene {
+        Some(ref path) => scene::load_description(path).and_then(|mut description| {
+            apply_overrides(&options, &mut description.settings);
+            description
+                .build()
+                .map_err(|e| format!("Invalid scene file {}: {}", path.display(), e))
+        }),
+        None => {
+            let mut settings = setup::default_settings();
+            apply_overrides(&options, &mut settings);
+            Ok(setup::setup(settings))
+        }
     };
 
-    for frame in frame_range {
-        let start = Instant::now();
+    let scene = scene.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+
+    let radius = options.filter_radius;
+    match options.filter {
+        FilterKind::BlackmanHarris => render(
+            &options,
+            scene,
+            radius.map_or_else(BlackmanHarrisFilter::default, BlackmanHarrisFilter::new),
+        ),
+        FilterKind::MitchellNetravali => render(
+            &options,
+            scene,
+            radius.map_or_else(MitchellNetravaliFilter::default, |r| {
+                MitchellNetravaliFilter::new(r, 1.0 / 3.0, 1.0 / 3.0)
+            }),
+        ),
+        FilterKind::Box => render(
+            &options,
+            scene,
+            radius.map_or_else(BoxFilter::default, BoxFilter::new),
+        ),
+        FilterKind::LanczosSinc => render(
+            &options,
+            scene,
+            radius.map_or_else(LanczosSincFilter::default, |r| LanczosSincFilter::new(r, r)),
+        ),
+    }
+}
+
+fn apply_overrides(options: &Options, settings: &mut RenderSettings) {
+    if let Some(res) = options.resolution {
+        settings.resolution = (res.w, res.h);
+    }
+    if let Some(samples) = options.samples {
+        settings.samples = samples;
+    }
+    if let Some(max_bounces) = options.max_bounces {
+        settings.max_bounces = max_bounces;
+    }
+    if let Some(volume_marches) = options.volume_marches {
+        settings.volume_marches = volume_marches;
+    }
+    if let Some(working_space) = options.working_space {
+        settings.working_space = working_space;
+    }
+    if options.spectral {
+        settings.spectral = true;
+    }
+}
+
+/// The channels the film needs to record to write the requested channels.
+fn film_channels(options: &Options) -> Vec<ChannelKind> {
+    let mut channels = Vec::new();
+    for &(kind, _) in options.write_channels.iter() {
+        // Color is written composited over the Background, or with coverage from Alpha.
+        let needed: &[ChannelKind] = match kind {
+            // The denoiser is guided by the first hits' features.
+            ChannelKind::Color if options.denoise.is_some() => &[
+                ChannelKind::Color,
+                ChannelKind::Alpha,
+                ChannelKind::Background,
+                ChannelKind::WorldNormal,
+                ChannelKind::Albedo,
+                ChannelKind::Depth,
+            ],
+            ChannelKind::Color => &[
+                ChannelKind::Color,
+                ChannelKind::Alpha,
+                ChannelKind::Background,
+            ],
+            // Depth and position previews are normalized over the pixels covered by surfaces.
+            ChannelKind::Depth => &[ChannelKind::Depth, ChannelKind::Alpha],
+            ChannelKind::WorldPosition => &[ChannelKind::WorldPosition, ChannelKind::Alpha],
+            _ => std::slice::from_ref(&kind),
+        };
+        for kind in needed {
+            if !channels.contains(kind) {
+                channels.push(*kind);
+            }
+        }
+    }
+    channels
+}
+
+/// `Film` is generic over its number of channels, so pick the length matching the channels to
+/// record at runtime.
+macro_rules! render_with_channels {
+    ($options:expr, $scene:expr, $filter:expr, $channels:expr, [$($n:literal => $len:ident),+]) => {
+        match $channels.len() {
+            $( $n => render_into::<_, $len>($options, $scene, $filter, &$channels), )+
+            n => panic!("Recording {} channels is not supported", n),
+        }
+    };
+}
+
+fn render<F: Filter>(options: &Options, scene: Scene, filter: F) {
+    let channels = film_channels(options);
+    render_with_channels!(options, scene, filter, channels, [
+        1 => U1, 2 => U2, 3 => U3, 4 => U4, 5 => U5, 6 => U6, 7 => U7, 8 => U8,
+        9 => U9, 10 => U10, 11 => U11, 12 => U12, 13 => U13, 14 => U14, 15 => U15, 16 => U16
+    ])
+}
+
+fn render_into<F, N>(options: &Options, scene: Scene, filter: F, channels: &[ChannelKind])
+where
+    F: Filter,
+    N: ArrayLength<ChannelStorage> + ArrayLength<ChannelTileStorage>,
+{
+    let Scene {
+        camera,
+        world,
+        settings,
+    } = scene;
+
+    let mut film = Film::<N>::new(channels, settings.resolution()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    film.set_color_space(settings.working_space);
+
+    if let Some(crop) = options.crop {
+        if crop.raster_bounds(settings.resolution()).is_empty() {
+            eprintln!("The crop window doesn't contain any pixels of the image");
+            std::process::exit(1);
+        }
+    }
+
+    // The light tracer splats all of its samples, so the pixels they are taken for never see any
+    // variance to decide where more samples are needed.
+    if options.adaptive.is_some()
+        && options.debug.is_none()
+        && options.integrator == IntegratorKind::LightTracing
+    {
+        eprintln!("Adaptive sampling can't be used with the light tracer");
+        std::process::exit(1);
+    }
 
-        let frame_start = frame as f32 * (1.0 / frame_rate as f32);
-        let frame_end = frame_start + shutter_speed;
+    // The denoiser needs to know how noisy each pixel is, which isn't measured for the light that
+    // the bidirectional path tracer and the light tracer splat.
+    if options.denoise.is_some() && options.debug.is_none() {
+        match options.integrator {
+            IntegratorKind::Bidirectional | IntegratorKind::LightTracing => {
+                eprintln!("Denoising can only be used with the path tracer");
+                std::process::exit(1);
+            }
+            IntegratorKind::PathTracing | IntegratorKind::AmbientOcclusion => (),
+        }
+    }
+
+    // Only the path tracer scatters light in volumes, the others would render them as if they only
+    // absorbed it.
+    if world.volume_params.coeff_scattering.is_some() && options.debug.is_none() {
+        match options.integrator {
+            IntegratorKind::Bidirectional | IntegratorKind::LightTracing => {
+                eprintln!(
+                    "The scene's volume scatters light, which only the path tracer can render"
+                );
+                std::process::exit(1);
+            }
+            IntegratorKind::PathTracing | IntegratorKind::AmbientOcclusion => (),
+        }
+    }
+
+    // Pixels outside the crop window aren't rendered, so they are only written if asked to.
+    let write_crop = if options.write_cropped {
+        options.crop
+    } else {
+        None
+    };
+
+    let mut post_effects: Vec<Box<dyn PostEffect>> = Vec::new();
+    if let Some(bloom) = options.bloom {
+        post_effects.push(Box::new(bloom));
+    }
+    if let Some(glare) = options.glare {
+        post_effects.push(Box::new(glare));
+    }
+
+    // Checkpoints are written between passes, so checkpointed frames are split into one pass per
+    // sample unless asked for a number of passes. Every pass gets at least one sample.
+    let passes = match (options.passes, options.checkpoint_interval) {
+        (Some(passes), _) => passes,
+        (None, Some(_)) => settings.samples,
+        (None, None) => 1,
+    }
+    .min(settings.samples);
+
+    let frame_budget = match options.adaptive {
+        Some(error_threshold) => SampleBudget::Adaptive(AdaptiveSampling {
+            min_samples: options.min_samples,
+            max_samples: settings.samples,
+            pass_samples: options.pass_samples,
+            error_threshold,
+        }),
+        None => SampleBudget::Fixed(settings.samples),
+    };
 
-        film.render_frame_into(
-            &world,
+    let path_tracer = PathTracingIntegrator {
+        max_bounces: settings.max_bounces,
+        volume_marches: settings.volume_marches,
+        spectral: settings.spectral,
+    };
+    let integrator: Box<dyn Integrator> = match (options.debug, options.integrator) {
+        (Some(view), _) => Box::new(DebugIntegrator {
+            view,
             camera,
-            &integrator,
-            &filter,
-            Extent2u::new(16, 16),
-            frame,
-            frame_start..frame_end,
-            crate::setup::SAMPLES,
-        );
+            path_tracer,
+        }),
+        (None, IntegratorKind::PathTracing) => Box::new(path_tracer),
+        (None, IntegratorKind::Bidirectional) => Box::new(BidirectionalIntegrator {
+            max_bounces: settings.max_bounces,
+            camera,
+            spectral: settings.spectral,
+        }),
+        (None, IntegratorKind::LightTracing) => Box::new(LightTracingIntegrator {
+            max_bounces: settings.max_bounces,
+            camera,
+            spectral: settings.spectral,
+        }),
+        (None, IntegratorKind::AmbientOcclusion) => Box::new(AmbientOcclusionIntegrator {
+            samples: options.ao_samples,
+            radius: options.ao_radius,
+        }),
+    };
+
+    for frame in options.frames.clone() {
+        let start = Instant::now();
+
+        let frame_start = frame as f32 * (1.0 / options.frame_rate);
+        let frame_end = frame_start + options.shutter_speed;
+
+        film.reset();
+        let mut last_preview = start;
+        let mut last_checkpoint = start;
+
+        let checkpoint_path = options.output_dir.join(format!("{:04}.checkpoint", frame));
+        if options.resume && checkpoint_path.exists() {
+            film.load_checkpoint(&checkpoint_path, frame_budget, passes)
+                .and_then(|_| match film.frame() {
+                    Some(f) if f == frame => Ok(()),
+                    _ => Err(format!(
+                        "Checkpoint {} is not of frame {}",
+                        checkpoint_path.display(),
+                        frame
+                    )),
+                })
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                });
+            println!("Resuming frame {} after {} passes.", frame, film.epoch());
+        }
+
+        for pass in film.epoch()..passes {
+            // Split the samples as evenly as possible between the passes.
+            let samples = settings.samples * (pass + 1) / passes - settings.samples * pass / passes;
+            let budget = match frame_budget {
+                SampleBudget::Fixed(_) => SampleBudget::Fixed(samples),
+                SampleBudget::Adaptive(adaptive) => SampleBudget::Adaptive(AdaptiveSampling {
+                    max_samples: samples,
+                    ..adaptive
+                }),
+            };
+
+            if passes > 1 {
+                println!("Pass {} of {}...", pass + 1, passes);
+            }
+
+            film.render_frame_into(
+                &world,
+                camera,
+                &*integrator,
+                &filter,
+                options.tile_size,
+                frame,
+                frame_start..frame_end,
+                budget,
+                options.crop,
+            );
+
+            if let Some(interval) = options.preview_interval {
+                let now = Instant::now();
+                if pass + 1 < passes && now - last_preview >= Duration::from_secs_f32(interval) {
+                    last_preview = now;
+                    if let Some(denoise) = options.denoise {
+                        film.denoise(&denoise).unwrap();
+                    }
+                    if !post_effects.is_empty() {
+                        film.post_process(&post_effects).unwrap();
+                    }
+                    film.save_to(
+                        &options.write_channels,
+                        &options.output_dir,
+                        format!("{:04}_preview", frame),
+                        false,
+                        options.display,
+                        write_crop,
+                    )
+                    .unwrap_or_else(|e| eprintln!("Failed to write preview: {}", e));
+                }
+            }
+
+            if let Some(interval) = options.checkpoint_interval {
+                let now = Instant::now();
+                if pass + 1 < passes && now - last_checkpoint >= Duration::from_secs_f32(interval) {
+                    last_checkpoint = now;
+                    film.save_checkpoint(&checkpoint_path, frame_budget, passes)
+                        .unwrap_or_else(|e| eprintln!("{}", e));
+                }
+            }
+        }
 
         let time = Instant::now() - start;
         let time_secs = time.as_secs();
@@ -83,16 +366,48 @@ fn main() {
 
         println!("Post processing image...");
 
+        let base_name = format!("{:04}_{}_spp", frame, settings.samples * 4);
+
+        if let Some(denoise) = options.denoise {
+            println!("Denoising...");
+            film.denoise(&denoise).unwrap();
+        }
+        if !post_effects.is_empty() {
+            film.post_process(&post_effects).unwrap();
+        }
+
         film.save_to(
-            &[
-                ChannelKind::Alpha,
-                ChannelKind::WorldNormal,
-                ChannelKind::Color,
-            ],
-            "renders",
-            format!("{}_spp", crate::setup::SAMPLES * 4),
+            &options.write_channels,
+            &options.output_dir,
+            base_name.clone(),
             false,
+            options.display,
+            write_crop,
         )
-        .unwrap();
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+
+        if let Some(precision) = options.exr {
+            film.save_exr(
+                &film.channel_kinds(),
+                &options.output_dir,
+                base_name,
+                precision,
+                write_crop,
+            )
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+        }
+
+        // The frame is finished, so its checkpoint is no longer needed.
+        if checkpoint_path.exists() {
+            std::fs::remove_file(&checkpoint_path).unwrap_or_else(|e| {
+                eprintln!("Failed to remove {}: {}", checkpoint_path.display(), e)
+            });
+        }
     }
 }
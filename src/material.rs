@@ -23,9 +23,18 @@ pub trait BSDF {
 
     fn f(&self, wo: Wec3, wi: Wec3, n: Wec3) -> WSrgb;
 
+    /// The pdf with respect to solid angle of `scatter` choosing direction `wi`.
+    fn pdf(&self, wo: Wec3, wi: Wec3, n: Wec3) -> f32x4;
+
     fn le(&self, _wo: Wec3, _intersection: &WShadingPoint) -> WSrgb {
         WSrgb::zero()
     }
+
+    /// An estimate of the fraction of light reflected towards `wo`, in `[0, 1]`. Used as a
+    /// feature buffer for denoising rather than for rendering.
+    fn albedo(&self, _wo: Wec3, _intersection: &WShadingPoint) -> WSrgb {
+        WSrgb::zero()
+    }
 }
 
 pub trait Material: Send + Sync {
@@ -72,6 +81,12 @@ impl MaterialStore {
     }
 }
 
+impl Default for MaterialStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub trait WShadingParamGenerator<T> {
     fn gen(&self, intersection: &WShadingPoint) -> T;
 }
@@ -136,9 +151,17 @@ impl BSDF for LambertianBSDF {
         }
     }
 
-    fn f(&self, _wi: Wec3, _wo: Wec3, _n: Wec3) -> WSrgb {
+    fn f(&self, _wo: Wec3, _wi: Wec3, _n: Wec3) -> WSrgb {
         self.albedo / f32x4::PI
     }
+
+    fn pdf(&self, _wo: Wec3, wi: Wec3, n: Wec3) -> f32x4 {
+        wi.dot(n).max(f32x4::ZERO) / f32x4::PI
+    }
+
+    fn albedo(&self, _wo: Wec3, _intersection: &WShadingPoint) -> WSrgb {
+        self.albedo
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -191,17 +214,45 @@ where
     }
 }
 
+impl DielectricBSDF {
+    /// The fraction of light reflected by the specular lobe rather than the diffuse one, which is
+    /// also the probability of `scatter` sampling it.
+    fn fresnel(&self, wo: Wec3, n: Wec3) -> f32x4 {
+        f_schlick(n.dot(wo).abs(), f32x4::from(0.04))
+    }
+
+    /// The Phong lobe around the mirror reflection of `wo`, i.e. `cos^roughness` of the angle
+    /// between it and `wi`.
+    fn specular_lobe(&self, wo: Wec3, wi: Wec3, n: Wec3) -> f32x4 {
+        let cos_alpha = reflect(wo, n).dot(wi).max(f32x4::ZERO);
+        cos_alpha.powf(self.roughness)
+    }
+}
+
+/// The mirror reflection of `wo` (pointing away from the surface) about `n`.
+fn reflect(wo: Wec3, n: Wec3) -> Wec3 {
+    n * (f32x4::from(2.0) * n.dot(wo)) - wo
+}
+
 impl BSDF for DielectricBSDF {
-    fn f(&self, wi: Wec3, wo: Wec3, n: Wec3) -> WSrgb {
-        let dot = f32x4::ZERO.max(wi.dot(n));
-        let fresnel = f_schlick(dot, f32x4::from(0.04));
-        let half = (wo + wi).normalized();
-        let cos_alpha = f32x4::ZERO.max(half.dot(n)).powf(self.roughness);
+    fn f(&self, wo: Wec3, wi: Wec3, n: Wec3) -> WSrgb {
+        let fresnel = self.fresnel(wo, n);
         let two = f32x4::from(2.0);
-        let spec_factor = cos_alpha * (self.roughness + two) / (two * f32x4::PI);
-        let spec_f = WSrgb::one() * spec_factor * fresnel;
-        let diffuse_f = self.albedo / f32x4::PI * (f32x4::ONE - fresnel);
-        spec_f + diffuse_f
+        let spec_f = WSrgb::one()
+            * (self.specular_lobe(wo, wi, n) * (self.roughness + two) / (two * f32x4::PI));
+        let diffuse_f = self.albedo / f32x4::PI;
+        let f = spec_f * fresnel + diffuse_f * (f32x4::ONE - fresnel);
+
+        let below_horizon = n.dot(wi).cmp_le(f32x4::ZERO);
+        WSrgb::merge(below_horizon, WSrgb::zero(), f)
+    }
+
+    fn pdf(&self, wo: Wec3, wi: Wec3, n: Wec3) -> f32x4 {
+        let fresnel = self.fresnel(wo, n);
+        let diffuse_pdf = n.dot(wi).max(f32x4::ZERO) / f32x4::PI;
+        let spec_pdf =
+            (self.roughness + f32x4::ONE) / f32x4::TWO_PI * self.specular_lobe(wo, wi, n);
+        fresnel * spec_pdf + (f32x4::ONE - fresnel) * diffuse_pdf
     }
 
     fn scatter(
@@ -212,48 +263,33 @@ impl BSDF for DielectricBSDF {
         samples_2d: &[f32x4; 4],
     ) -> WScatteringEvent {
         let norm = intersection.normal;
-        let cos = norm.dot(wo).abs();
-        let two = f32x4::from(2.0);
 
         // diffuse part
         let diffuse_sample = Wec3::cosine_weighted_in_hemisphere(array_ref![samples_2d, 0, 2]);
         let diffuse_bounce = (intersection.basis * diffuse_sample).normalized();
-        // in this case diffuse_sample.z = diffuse_sample.dot(Wec3::unit_z())
-        // because using intersection coordinate system basis
-        let diffuse_pdf = f32x4::from(0.00001).max(diffuse_sample.z / f32x4::PI);
-        let diffuse_f = self.albedo / f32x4::PI;
 
         // spec part
         let spec_sample = Wec3::cosine_power_weighted(array_ref![samples_2d, 2, 2], self.roughness);
-
-        let reflection = wo.reflected(norm);
-        let basis = reflection.get_orthonormal_basis();
-
+        let basis = reflect(wo, norm).get_orthonormal_basis();
         let spec_bounce = (basis * spec_sample).normalized();
 
-        // in this case spec_sample.z = spec_sample.dot(Wec3::unit_z()) = cos_alpha
-        // because using reflection coordinate system basis
-        let cos_alpha_pow = spec_sample.z.powf(self.roughness).max(f32x4::EPSILON);
-
-        let spec_pdf = (self.roughness + f32x4::ONE) / f32x4::TWO_PI * cos_alpha_pow;
-
-        let spec_coeff = (self.roughness + two) / f32x4::TWO_PI * cos_alpha_pow;
-        let below_horizon = norm.dot(spec_bounce).cmp_lt(f32x4::ZERO);
-        let spec_coeff = f32x4::merge(below_horizon, f32x4::ZERO, spec_coeff);
-
-        let spec_f = WSrgb::one() * spec_coeff;
-
-        // merge by fresnel
-        let fresnel = f_schlick(cos, f32x4::from(0.04));
-        let fresnel_sample = f32x4::from(samples_1d);
-        let fresnel_mask = fresnel_sample.cmp_lt(fresnel);
+        // choose a lobe by fresnel, and then evaluate the whole BSDF in the chosen direction
+        let fresnel_mask = samples_1d.cmp_lt(self.fresnel(wo, norm));
+        let wi = Wec3::merge(fresnel_mask, spec_bounce, diffuse_bounce);
 
         WScatteringEvent {
-            wi: Wec3::merge(fresnel_mask, spec_bounce, diffuse_bounce),
-            f: WSrgb::merge(fresnel_mask, spec_f, diffuse_f),
-            pdf: fresnel * spec_pdf + (f32x4::ONE - fresnel) * diffuse_pdf,
+            wi,
+            f: self.f(wo, wi, norm),
+            pdf: f32x4::from(0.00001).max(self.pdf(wo, wi, norm)),
         }
     }
+
+    fn albedo(&self, wo: Wec3, intersection: &WShadingPoint) -> WSrgb {
+        // The white specular lobe reflects the fresnel term and the diffuse albedo the rest.
+        let cos = intersection.normal.dot(wo).abs();
+        let fresnel = f_schlick(cos, f32x4::from(0.04));
+        self.albedo * (f32x4::ONE - fresnel) + WSrgb::one() * fresnel
+    }
 }
 
 // #[allow(dead_code)]
@@ -428,7 +464,12 @@ impl BSDF for SkyBSDF {
     }
 
     fn f(&self, _: Wec3, _: Wec3, _: Wec3) -> WSrgb {
-        panic!()
+        WSrgb::zero()
+    }
+
+    /// The sky doesn't scatter light, so it never samples any direction.
+    fn pdf(&self, _: Wec3, _: Wec3, _: Wec3) -> f32x4 {
+        f32x4::ZERO
     }
 
     fn scatter(
@@ -504,6 +545,10 @@ where
         WSrgb::zero()
     }
 
+    fn pdf(&self, wo: Wec3, wi: Wec3, n: Wec3) -> f32x4 {
+        self.inner.pdf(wo, wi, n)
+    }
+
     fn scatter(
         &self,
         wo: Wec3,
@@ -517,4 +562,11 @@ where
     fn le(&self, _wo: Wec3, _intersection: &WShadingPoint) -> WSrgb {
         self.emission
     }
+
+    fn albedo(&self, _wo: Wec3, _intersection: &WShadingPoint) -> WSrgb {
+        // Emitters don't reflect meaningfully, so use the hue of the emission to keep them
+        // distinct from their surroundings.
+        let max = self.emission.max_channel().max(f32x4::EPSILON);
+        self.emission / max
+    }
 }
@@ -10,6 +10,7 @@ pub type Vec2 = ultraviolet::Vec2;
 pub type Wec2 = ultraviolet::Wec2;
 pub type Vec2u = ultraviolet::int::Vec2u;
 
+pub type Mat3 = ultraviolet::Mat3;
 pub type Wat3 = ultraviolet::Wat3;
 
 #[derive(Clone, Copy, Debug)]
@@ -34,6 +35,21 @@ impl Bounds2u {
     pub fn size(&self) -> Extent2u {
         Extent2u::new(self.max.x - self.min.x, self.max.y - self.min.y)
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.max.x <= self.min.x || self.max.y <= self.min.y
+    }
+
+    /// The overlap of two bounds, which is empty (but still has a valid size) if they don't
+    /// overlap.
+    pub fn intersect(&self, other: &Bounds2u) -> Bounds2u {
+        let min = Vec2u::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y));
+        let max = Vec2u::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y));
+        Bounds2u {
+            min,
+            max: Vec2u::new(max.x.max(min.x), max.y.max(min.y)),
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -104,11 +120,10 @@ impl RandomSample3d<f32x4> for Wec3 {
 
     // pdf: (power+1)/ 2pi * cos^power(alpha)
     fn cosine_power_weighted(samples: &[f32x4; 2], power: f32x4) -> Self {
-        let two = f32x4::from(2.0);
         let a = samples[0].powf(f32x4::ONE / (power + f32x4::ONE));
         let a2 = a * a;
         let b = (f32x4::ONE - a2).sqrt();
-        let (s, c) = (two * samples[1]).sin_cos();
+        let (s, c) = (f32x4::TWO_PI * samples[1]).sin_cos();
         Wec3::new(b * c, b * s, a)
     }
 }
@@ -190,12 +205,30 @@ impl CDF {
     }
 }
 
+impl Default for CDF {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The multiple importance sampling weight of a sample taken with `n_samples_f` samples of the
+/// strategy with pdf `f_pdf`, when it is combined with `n_samples_g` samples of the strategy with
+/// pdf `g_pdf` (Veach 1997). It's zero where neither strategy can take the sample.
 #[inline]
-#[allow(dead_code)]
-pub fn power_heuristic(n_samples_f: usize, f_pdf: f32, n_samples_g: usize, g_pdf: f32) -> f32 {
-    let f = n_samples_f as f32 * f_pdf;
-    let g = n_samples_g as f32 * g_pdf;
-    f * f / (f * f + g * g)
+pub fn power_heuristic(
+    n_samples_f: usize,
+    f_pdf: f32x4,
+    n_samples_g: usize,
+    g_pdf: f32x4,
+) -> f32x4 {
+    let f = f32x4::from(n_samples_f as f32) * f_pdf;
+    let g = f32x4::from(n_samples_g as f32) * g_pdf;
+    let denominator = f * f + g * g;
+    f32x4::merge(
+        denominator.cmp_gt(f32x4::ZERO),
+        f * f / denominator,
+        f32x4::ZERO,
+    )
 }
 
 pub fn concentric_circle_map(uv: &[f32x4; 2]) -> Wec2 {
@@ -0,0 +1,255 @@
+use rayon::prelude::*;
+
+use crate::math::{Extent2u, Vec2, Vec3};
+use crate::spectrum::Srgb;
+
+/// An effect applied to the linear HDR color of a film before it is tone mapped, see
+/// `Film::post_process`.
+pub trait PostEffect: Send + Sync {
+    /// Apply the effect in place to an image of resolution `res`, stored row by row.
+    fn apply(&self, color: &mut [Srgb], res: Extent2u);
+}
+
+/// Bloom, i.e. the glow around bright highlights caused by light scattering in the lens and the
+/// eye. A fraction `intensity` of the energy above `threshold` in each pixel is spread out with a
+/// heavy tailed point spread function, approximated by the sum of Gaussians of radius
+/// `radius`, `2 * radius`, `4 * radius` and so on for `levels` levels. Energy is only moved
+/// around, not added.
+#[derive(Clone, Copy, Debug)]
+pub struct Bloom {
+    pub threshold: f32,
+    pub intensity: f32,
+    /// The standard deviation in pixels of the narrowest Gaussian.
+    pub radius: f32,
+    pub levels: usize,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom {
+            threshold: 1.0,
+            intensity: 0.1,
+            radius: 2.0,
+            levels: 5,
+        }
+    }
+}
+
+impl PostEffect for Bloom {
+    fn apply(&self, color: &mut [Srgb], res: Extent2u) {
+        let bright = over_threshold(color, self.threshold, self.intensity);
+        if self.levels == 0 {
+            return;
+        }
+
+        let mut bloom = vec![Vec3::zero(); bright.len()];
+        let level_weight = 1.0 / self.levels as f32;
+        for level in 0..self.levels {
+            let sigma = self.radius * (1 << level) as f32;
+            let blurred = gaussian_blur(&bright, res, sigma);
+            for (b, x) in bloom.iter_mut().zip(blurred.iter()) {
+                *b += *x * level_weight;
+            }
+        }
+
+        for ((c, b), x) in color.iter_mut().zip(bloom.iter()).zip(bright.iter()) {
+            c.0 += *b - *x;
+        }
+    }
+}
+
+/// Lens star glare, i.e. the streaks around bright highlights caused by diffraction on the
+/// aperture blades. A fraction `intensity` of the energy above `threshold` in each pixel is spread
+/// along `streaks` evenly spaced directions, starting at `rotation` radians counterclockwise from
+/// the +x axis, falling off exponentially so that it is mostly gone after `length` pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct Glare {
+    pub threshold: f32,
+    pub intensity: f32,
+    pub streaks: usize,
+    pub length: f32,
+    pub rotation: f32,
+}
+
+impl Default for Glare {
+    fn default() -> Self {
+        Glare {
+            threshold: 1.0,
+            intensity: 0.05,
+            streaks: 6,
+            length: 64.0,
+            rotation: std::f32::consts::FRAC_PI_4 / 3.0,
+        }
+    }
+}
+
+impl PostEffect for Glare {
+    fn apply(&self, color: &mut [Srgb], res: Extent2u) {
+        let bright = over_threshold(color, self.threshold, self.intensity);
+        if self.streaks == 0 {
+            return;
+        }
+
+        // Each streak is built up in passes of 4 taps, the spacing between which grows by 4x each
+        // pass (Kawase 2003), so a streak reaching `length` pixels takes only log4(length) passes.
+        const TAPS: usize = 4;
+        let passes = self.length.max(1.0).log(TAPS as f32).ceil().max(1.0) as u32;
+        // The falloff per pixel, such that a streak is down to 1% after `length` pixels.
+        let falloff = 0.01f32.powf(1.0 / self.length.max(1.0));
+
+        let mut glare = vec![Vec3::zero(); bright.len()];
+        let streak_weight = 1.0 / self.streaks as f32;
+        for streak in 0..self.streaks {
+            let angle =
+                self.rotation + streak as f32 * 2.0 * std::f32::consts::PI / self.streaks as f32;
+            let direction = Vec2::new(angle.cos(), angle.sin());
+
+            let mut streak_image = bright.clone();
+            for pass in 0..passes {
+                let spacing = TAPS.pow(pass) as f32;
+                let weights = (0..TAPS)
+                    .map(|tap| falloff.powf(tap as f32 * spacing))
+                    .collect::<Vec<_>>();
+                let total = weights.iter().sum::<f32>();
+
+                let input = &streak_image;
+                streak_image = (0..bright.len())
+                    .into_par_iter()
+                    .map(|idx| {
+                        let pixel =
+                            Vec2::new((idx % res.w as usize) as f32, (idx / res.w as usize) as f32);
+                        weights
+                            .iter()
+                            .enumerate()
+                            .map(|(tap, weight)| {
+                                // Gathering from behind spreads energy forward along the streak.
+                                let from = pixel - direction * (tap as f32 * spacing);
+                                sample_bilinear(input, res, from) * (*weight / total)
+                            })
+                            .fold(Vec3::zero(), |a, b| a + b)
+                    })
+                    .collect();
+            }
+
+            for (g, s) in glare.iter_mut().zip(streak_image.iter()) {
+                *g += *s * streak_weight;
+            }
+        }
+
+        for ((c, g), x) in color.iter_mut().zip(glare.iter()).zip(bright.iter()) {
+            c.0 += *g - *x;
+        }
+    }
+}
+
+/// The fraction `intensity` of the energy of each pixel above `threshold`. The color is scaled
+/// rather than clamped per channel, so highlights keep their hue.
+fn over_threshold(color: &[Srgb], threshold: f32, intensity: f32) -> Vec<Vec3> {
+    color
+        .iter()
+        .map(|c| {
+            let brightness = c.0.component_max();
+            if brightness > threshold && brightness.is_finite() {
+                c.0 * ((brightness - threshold) / brightness * intensity)
+            } else {
+                Vec3::zero()
+            }
+        })
+        .collect()
+}
+
+/// Look up an image at a fractional pixel position, with pixels outside it being black.
+fn sample_bilinear(image: &[Vec3], res: Extent2u, pos: Vec2) -> Vec3 {
+    let (x0, y0) = (pos.x.floor(), pos.y.floor());
+    let (fx, fy) = (pos.x - x0, pos.y - y0);
+    let texel = |x: f32, y: f32| {
+        if x < 0.0 || y < 0.0 || x >= res.w as f32 || y >= res.h as f32 {
+            Vec3::zero()
+        } else {
+            image[x as usize + y as usize * res.w as usize]
+        }
+    };
+    texel(x0, y0) * ((1.0 - fx) * (1.0 - fy))
+        + texel(x0 + 1.0, y0) * (fx * (1.0 - fy))
+        + texel(x0, y0 + 1.0) * ((1.0 - fx) * fy)
+        + texel(x0 + 1.0, y0 + 1.0) * (fx * fy)
+}
+
+/// Blur an image with a Gaussian of standard deviation `sigma` pixels, approximated by three
+/// successive box blurs in each direction, which costs the same no matter how wide it is.
+fn gaussian_blur(image: &[Vec3], res: Extent2u, sigma: f32) -> Vec<Vec3> {
+    let (w, h) = (res.w as usize, res.h as usize);
+
+    // The box widths whose successive application best matches the Gaussian, from "Fast Almost-
+    // Gaussian Filtering" (Kovesi 2010).
+    const BOXES: usize = 3;
+    let ideal_width = (12.0 * sigma * sigma / BOXES as f32 + 1.0).sqrt();
+    let mut lower = ideal_width.floor() as usize;
+    if lower % 2 == 0 {
+        lower = lower.saturating_sub(1).max(1);
+    }
+    let upper = lower + 2;
+    let lower_f = lower as f32;
+    let boxes_lower = ((12.0 * sigma * sigma
+        - (BOXES as f32) * lower_f * lower_f
+        - 4.0 * BOXES as f32 * lower_f
+        - 3.0 * BOXES as f32)
+        / (-4.0 * lower_f - 4.0))
+        .round()
+        .max(0.0) as usize;
+
+    let mut horizontal = image.to_vec();
+    let mut transposed = vec![Vec3::zero(); w * h];
+    for i in 0..BOXES {
+        let radius = (if i < boxes_lower { lower } else { upper }) / 2;
+        box_blur_rows(&mut horizontal, w, radius);
+    }
+    transpose(&horizontal, &mut transposed, w, h);
+    for i in 0..BOXES {
+        let radius = (if i < boxes_lower { lower } else { upper }) / 2;
+        box_blur_rows(&mut transposed, h, radius);
+    }
+    transpose(&transposed, &mut horizontal, h, w);
+    horizontal
+}
+
+/// Replace each pixel with the mean of the `2 * radius + 1` pixels around it in its row, with the
+/// row mirrored past its ends. What a pixel near an edge would spread out of the image is reflected
+/// back into it, so the blur keeps all of the row's energy.
+fn box_blur_rows(image: &mut [Vec3], width: usize, radius: usize) {
+    if radius == 0 {
+        return;
+    }
+    let scale = 1.0 / (2 * radius + 1) as f32;
+    image.par_chunks_mut(width).for_each(|row| {
+        let input = row.to_vec();
+        let at = |x: isize| {
+            // Mirrored at both ends, the row repeats every `2 * width` pixels.
+            let x = x.rem_euclid(2 * width as isize) as usize;
+            if x < width {
+                input[x]
+            } else {
+                input[2 * width - 1 - x]
+            }
+        };
+        let mut sum = (-(radius as isize)..=radius as isize)
+            .map(at)
+            .fold(Vec3::zero(), |a, b| a + b);
+        for (x, out) in row.iter_mut().enumerate() {
+            *out = sum * scale;
+            let x = x as isize;
+            sum += at(x + radius as isize + 1) - at(x - radius as isize);
+        }
+    });
+}
+
+fn transpose(input: &[Vec3], output: &mut [Vec3], width: usize, height: usize) {
+    output
+        .par_chunks_mut(height)
+        .enumerate()
+        .for_each(|(x, column)| {
+            for (y, out) in column.iter_mut().enumerate() {
+                *out = input[x + y * width];
+            }
+        });
+}
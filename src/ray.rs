@@ -10,6 +10,12 @@ macro_rules! rays {
             pub dir: $t,
             pub radiance: $st,
             pub throughput: $st,
+            /// The wavelengths in nanometers `radiance` and `throughput` are sampled at when
+            /// rendering spectrally, see `spectrum::sample_wavelengths`.
+            pub wavelengths: $t,
+            /// The pdf with respect to solid angle the ray's direction was sampled with, or zero
+            /// for camera rays.
+            pub pdf: $tt,
             pub tile_coord: $tc,
             pub valid: $bt,
             pub scramble: $scramt,
@@ -44,6 +50,8 @@ impl Ray {
             dir,
             radiance: Srgb::zero(),
             throughput: Srgb::one(),
+            wavelengths: Vec3::zero(),
+            pdf: 0.0,
             tile_coord,
             valid: true,
             scramble,
@@ -58,6 +66,8 @@ impl Ray {
             dir: Vec3::broadcast(std::f32::NAN),
             radiance: Srgb::zero(),
             throughput: Srgb::zero(),
+            wavelengths: Vec3::zero(),
+            pdf: 0.0,
             tile_coord: Vec2u::zero(),
             valid: false,
             scramble: 0f32,
@@ -82,6 +92,8 @@ impl WRay {
             dir,
             radiance: WSrgb::zero(),
             throughput: WSrgb::one(),
+            wavelengths: Wec3::zero(),
+            pdf: f32x4::ZERO,
             tile_coord,
             valid,
             scramble,
@@ -134,6 +146,13 @@ impl From<[Ray; 4]> for WRay {
                 rays[2].throughput,
                 rays[3].throughput,
             ]),
+            wavelengths: Wec3::from([
+                rays[0].wavelengths,
+                rays[1].wavelengths,
+                rays[2].wavelengths,
+                rays[3].wavelengths,
+            ]),
+            pdf: f32x4::from([rays[0].pdf, rays[1].pdf, rays[2].pdf, rays[3].pdf]),
             tile_coord: [
                 rays[0].tile_coord,
                 rays[1].tile_coord,
@@ -160,10 +179,12 @@ impl From<[Ray; 4]> for WRay {
 impl Into<[Ray; 4]> for WRay {
     fn into(self) -> [Ray; 4] {
         let times = self.time.as_ref();
+        let pdfs = self.pdf.as_ref();
         let origins: [Vec3; 4] = self.origin.into();
         let dirs: [Vec3; 4] = self.dir.into();
         let throughputs: [Srgb; 4] = self.throughput.into();
         let radiances: [Srgb; 4] = self.radiance.into();
+        let wavelengths: [Vec3; 4] = self.wavelengths.into();
         [
             Ray {
                 time: times[0],
@@ -171,6 +192,8 @@ impl Into<[Ray; 4]> for WRay {
                 dir: dirs[0],
                 radiance: radiances[0],
                 throughput: throughputs[0],
+                wavelengths: wavelengths[0],
+                pdf: pdfs[0],
                 tile_coord: self.tile_coord[0],
                 valid: self.valid[0],
                 scramble: self.scramble[0],
@@ -182,6 +205,8 @@ impl Into<[Ray; 4]> for WRay {
                 dir: dirs[1],
                 radiance: radiances[1],
                 throughput: throughputs[1],
+                wavelengths: wavelengths[1],
+                pdf: pdfs[1],
                 tile_coord: self.tile_coord[1],
                 valid: self.valid[1],
                 scramble: self.scramble[1],
@@ -193,6 +218,8 @@ impl Into<[Ray; 4]> for WRay {
                 dir: dirs[2],
                 radiance: radiances[2],
                 throughput: throughputs[2],
+                wavelengths: wavelengths[2],
+                pdf: pdfs[2],
                 tile_coord: self.tile_coord[2],
                 valid: self.valid[2],
                 scramble: self.scramble[2],
@@ -204,6 +231,8 @@ impl Into<[Ray; 4]> for WRay {
                 dir: dirs[3],
                 radiance: radiances[3],
                 throughput: throughputs[3],
+                wavelengths: wavelengths[3],
+                pdf: pdfs[3],
                 tile_coord: self.tile_coord[3],
                 valid: self.valid[3],
                 scramble: self.scramble[3],
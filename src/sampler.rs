@@ -2,12 +2,6 @@ use rand::prelude::*;
 
 use ultraviolet::f32x4;
 
-// pub trait Sampler {
-//     fn begin_pixel(&mut self, pixel: Vec2u);
-//     fn request_samples_2d(&mut self, samples: usize);
-//     fn request_samples_1d(&mut self, samples: usize);
-// }
-
 pub struct Samples {
     pub samples: usize,
     pub samples_1d: Vec<f32>,
@@ -125,3 +119,145 @@ impl Samples {
         ])
     }
 }
+
+/// Describes where the sample sets used at each bounce depth live within a `Samples`.
+///
+/// Integrators request a number of 1d and 2d sample sets for every depth they will be called at,
+/// and the layout lays them out one depth after the other after the sets which are reserved for
+/// generating camera rays.
+pub struct SampleLayout {
+    offsets_1d: Vec<usize>,
+    offsets_2d: Vec<usize>,
+}
+
+impl SampleLayout {
+    pub fn new<F1, F2>(
+        max_depth: usize,
+        reserved_1d: usize,
+        reserved_2d: usize,
+        sets_1d_at: F1,
+        sets_2d_at: F2,
+    ) -> Self
+    where
+        F1: Fn(usize) -> usize,
+        F2: Fn(usize) -> usize,
+    {
+        let mut offsets_1d = Vec::with_capacity(max_depth + 2);
+        let mut offsets_2d = Vec::with_capacity(max_depth + 2);
+        offsets_1d.push(reserved_1d);
+        offsets_2d.push(reserved_2d);
+
+        for depth in 0..=max_depth {
+            offsets_1d.push(offsets_1d[depth] + sets_1d_at(depth));
+            offsets_2d.push(offsets_2d[depth] + sets_2d_at(depth));
+        }
+
+        Self {
+            offsets_1d,
+            offsets_2d,
+        }
+    }
+
+    /// The total number of 1d sample sets, including reserved ones.
+    pub fn total_1d(&self) -> usize {
+        *self.offsets_1d.last().unwrap()
+    }
+
+    /// The total number of 2d sample sets, including reserved ones.
+    pub fn total_2d(&self) -> usize {
+        *self.offsets_2d.last().unwrap()
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.offsets_1d.len() - 2
+    }
+
+    /// Get a provider for the sample sets at `depth` for a set of 4 samples.
+    pub fn provider<'a>(
+        &self,
+        samples: &'a Samples,
+        depth: usize,
+        sample_nums: [usize; 4],
+        scrambles: [f32; 4],
+    ) -> WSampleProvider<'a> {
+        assert!(
+            depth <= self.max_depth(),
+            "Requested samples for depth {} but sample layout only goes to depth {}",
+            depth,
+            self.max_depth()
+        );
+
+        WSampleProvider {
+            samples,
+            sample_nums,
+            scrambles,
+            offset_1d: self.offsets_1d[depth],
+            len_1d: self.offsets_1d[depth + 1] - self.offsets_1d[depth],
+            offset_2d: self.offsets_2d[depth],
+            len_2d: self.offsets_2d[depth + 1] - self.offsets_2d[depth],
+        }
+    }
+}
+
+/// Provides the sample sets an integrator requested for one bounce depth, for 4 samples at once.
+///
+/// Dimensions are relative to the start of this depth, so dimension 0 is always the first set
+/// requested for this depth.
+#[derive(Clone, Copy)]
+pub struct WSampleProvider<'a> {
+    samples: &'a Samples,
+    sample_nums: [usize; 4],
+    scrambles: [f32; 4],
+    offset_1d: usize,
+    len_1d: usize,
+    offset_2d: usize,
+    len_2d: usize,
+}
+
+impl<'a> WSampleProvider<'a> {
+    /// The number of 1d sample sets available
+    pub fn len_1d(&self) -> usize {
+        self.len_1d
+    }
+
+    /// The number of 2d sample sets available
+    pub fn len_2d(&self) -> usize {
+        self.len_2d
+    }
+
+    #[inline]
+    pub fn sample_1d(&self, dim: usize) -> f32x4 {
+        assert!(
+            dim < self.len_1d,
+            "Requested 1d sample set {} but only {} were requested",
+            dim,
+            self.len_1d
+        );
+        self.samples
+            .wide_sample_1d_array(self.sample_nums, self.scrambles, self.offset_1d + dim)
+    }
+
+    #[inline]
+    pub fn sample_2d(&self, dim: usize) -> [f32x4; 2] {
+        assert!(
+            dim < self.len_2d,
+            "Requested 2d sample set {} but only {} were requested",
+            dim,
+            self.len_2d
+        );
+        [
+            self.samples.wide_sample_2d_array(
+                0,
+                self.sample_nums,
+                self.scrambles,
+                self.offset_2d + dim,
+            ),
+            self.samples.wide_sample_2d_array(
+                1,
+                self.sample_nums,
+                self.scrambles,
+                self.offset_2d + dim,
+            ),
+        ]
+    }
+}
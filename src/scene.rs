@@ -0,0 +1,603 @@
+use serde::Deserialize;
+
+use crate::camera::{
+    Camera, CameraHandle, CameraStore, OrthographicCamera, PinholeCamera, ThinLensCamera,
+};
+use crate::color::ColorSpace;
+use crate::hitable::HitableStore;
+use crate::light::{Light, SphereLight};
+use crate::material::{Dielectric, Emissive, Lambertian, MaterialHandle, MaterialStore, Sky};
+use crate::math::{f32x4, Extent2u, Vec2, Vec3};
+use crate::sdf::{BoxFold, MandelBox, SphereFold, TracedSDF};
+use crate::spectrum::{Srgb, WSrgb};
+use crate::sphere::Sphere;
+use crate::volume::VolumeParams;
+use crate::world::World;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+type Triple = (f32, f32, f32);
+
+fn vec3(t: Triple) -> Vec3 {
+    Vec3::new(t.0, t.1, t.2)
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+fn default_volume_marches() -> usize {
+    2
+}
+
+/// Settings which control how a scene is rendered rather than what is in it.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderSettings {
+    /// The resolution of the output image
+    pub resolution: (u32, u32),
+    /// The number of samples per pixel. This number will actually get multiplied by 4.
+    pub samples: usize,
+    /// The number of times light will bounce around the scene before being killed.
+    pub max_bounces: usize,
+    /// The number of points along each ray to sample for volume scattering.
+    #[serde(default = "default_volume_marches")]
+    pub volume_marches: usize,
+    /// The default level of detail to render SDFs with. Closer to 0 = smaller detail will be shown.
+    pub sdf_detail_scale: f32,
+    /// The radius of the world. Rays which travel further than twice this are considered escaped.
+    pub world_radius: f32,
+    /// The linear color space light is rendered in. Colors in the scene are converted into it, and
+    /// the rendered image is in it.
+    #[serde(default)]
+    pub working_space: ColorSpace,
+    /// Render with sampled wavelengths instead of RGB, see `PathTracingIntegrator::spectral`.
+    #[serde(default)]
+    pub spectral: bool,
+}
+
+impl RenderSettings {
+    pub fn resolution(&self) -> Extent2u {
+        Extent2u::new(self.resolution.0, self.resolution.1)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.resolution.0 == 0 || self.resolution.1 == 0 {
+            return Err(format!(
+                "settings: resolution must be non-zero, got {}x{}",
+                self.resolution.0, self.resolution.1
+            ));
+        }
+        if self.samples == 0 {
+            return Err(String::from("settings: samples must be at least 1"));
+        }
+        check_positive("settings", "sdf_detail_scale", self.sdf_detail_scale)?;
+        check_positive("settings", "world_radius", self.world_radius)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VolumeDescription {
+    #[serde(default)]
+    pub scattering: Option<f32>,
+    #[serde(default)]
+    pub extinction: Option<f32>,
+    /// Scales `extinction` for each color, in the scene's `color_space`. Defaults to white, which
+    /// dims every color alike.
+    #[serde(default)]
+    pub extinction_color: Option<Triple>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum MaterialDescription {
+    Lambertian {
+        albedo: Triple,
+        /// Overrides the scene's `color_space` for this material's colors.
+        #[serde(default)]
+        color_space: Option<ColorSpace>,
+    },
+    /// Roughness should be between 0.0 (smooth) and 1.0 (rough)
+    Dielectric {
+        albedo: Triple,
+        roughness: f32,
+        #[serde(default)]
+        color_space: Option<ColorSpace>,
+    },
+    Emissive {
+        emission: Triple,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+        #[serde(default)]
+        color_space: Option<ColorSpace>,
+    },
+    Sky {
+        top: Triple,
+        bottom: Triple,
+        #[serde(default)]
+        color_space: Option<ColorSpace>,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum SdfDescription {
+    MandelBox {
+        iterations: usize,
+        box_fold: f32,
+        sphere_fold: (f32, f32),
+        scale: f32,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum HitableDescription {
+    Sphere {
+        center: Triple,
+        radius: f32,
+        material: String,
+    },
+    TracedSDF {
+        sdf: SdfDescription,
+        material: String,
+        /// Overrides the `sdf_detail_scale` from the render settings for this SDF.
+        #[serde(default)]
+        detail_scale: Option<f32>,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum LightDescription {
+    SphereLight {
+        position: Triple,
+        radius: f32,
+        emission: Triple,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+        /// Overrides the scene's `color_space` for this light's emission.
+        #[serde(default)]
+        color_space: Option<ColorSpace>,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum CameraDescription {
+    Pinhole {
+        vfov: f32,
+        origin: Triple,
+        at: Triple,
+        up: Triple,
+    },
+    ThinLens {
+        vfov: f32,
+        aperture: f32,
+        origin: Triple,
+        at: Triple,
+        up: Triple,
+        focus: Triple,
+    },
+    Orthographic {
+        vertical_size: f32,
+        origin: Triple,
+        at: Triple,
+        up: Triple,
+    },
+}
+
+/// A serialized description of everything needed to render a scene.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename = "Scene", deny_unknown_fields)]
+pub struct SceneDescription {
+    pub settings: RenderSettings,
+    /// The linear color space the colors of materials and lights are given in, unless they say
+    /// otherwise.
+    #[serde(default)]
+    pub color_space: ColorSpace,
+    #[serde(default)]
+    pub volume: VolumeDescription,
+    pub materials: BTreeMap<String, MaterialDescription>,
+    pub hitables: Vec<HitableDescription>,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+    pub camera: CameraDescription,
+}
+
+/// A fully built scene, ready to be rendered.
+pub struct Scene {
+    pub camera: CameraHandle,
+    pub world: World,
+    pub settings: RenderSettings,
+}
+
+/// Loads a scene description from a RON scene description file without building it, so that
+/// its settings can be changed first.
+pub fn load_description<P: AsRef<Path>>(path: P) -> Result<SceneDescription, String> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read scene file {}: {}", path.display(), e))?;
+
+    ron::de::from_str(&source)
+        .map_err(|e| format!("Failed to parse scene file {}: {}", path.display(), e))
+}
+
+/// Loads and builds a scene from a RON scene description file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, String> {
+    let path = path.as_ref();
+    load_description(path)?
+        .build()
+        .map_err(|e| format!("Invalid scene file {}: {}", path.display(), e))
+}
+
+fn check_positive(what: &str, name: &str, value: f32) -> Result<(), String> {
+    if value > 0.0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}: {} must be positive, got {}",
+            what, name, value
+        ))
+    }
+}
+
+fn check_color(what: &str, name: &str, color: Triple) -> Result<(), String> {
+    if color.0 >= 0.0 && color.1 >= 0.0 && color.2 >= 0.0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}: {} must not have negative (or NaN) components, got {:?}",
+            what, name, color
+        ))
+    }
+}
+
+impl SceneDescription {
+    pub fn build(self) -> Result<Scene, String> {
+        let settings = self.settings;
+        settings.validate()?;
+
+        let mut materials = MaterialStore::new();
+        let mut hitables = HitableStore::new();
+        let mut lights: Vec<Box<dyn Light>> = Vec::new();
+
+        // Convert a color given in `space` (or the scene's color space) to the working space.
+        // Colors outside the working space's gamut are clamped to it.
+        let scene_space = self.color_space;
+        let color = |t: Triple, space: Option<ColorSpace>| {
+            let converted = space
+                .unwrap_or(scene_space)
+                .convert(vec3(t), settings.working_space);
+            Srgb(converted.map(|x| x.max(0.0)))
+        };
+
+        let mut material_handles = BTreeMap::new();
+        for (name, material) in self.materials.iter() {
+            let what = format!("material \"{}\"", name);
+            let handle = match *material {
+                MaterialDescription::Lambertian {
+                    albedo,
+                    color_space,
+                } => {
+                    check_color(&what, "albedo", albedo)?;
+                    materials
+                        .add_material(Lambertian::new(WSrgb::splat(color(albedo, color_space))))
+                }
+                MaterialDescription::Dielectric {
+                    albedo,
+                    roughness,
+                    color_space,
+                } => {
+                    check_color(&what, "albedo", albedo)?;
+                    if !(0.0..=1.0).contains(&roughness) {
+                        return Err(format!(
+                            "{}: roughness must be between 0.0 and 1.0, got {}",
+                            what, roughness
+                        ));
+                    }
+                    materials
+                        .add_material(Dielectric::new_remap(color(albedo, color_space), roughness))
+                }
+                MaterialDescription::Emissive {
+                    emission,
+                    intensity,
+                    color_space,
+                } => {
+                    check_color(&what, "emission", emission)?;
+                    materials.add_material(Emissive::new_splat(
+                        color(emission, color_space) * intensity,
+                    ))
+                }
+                MaterialDescription::Sky {
+                    top,
+                    bottom,
+                    color_space,
+                } => {
+                    check_color(&what, "top", top)?;
+                    check_color(&what, "bottom", bottom)?;
+                    materials.add_material(Sky::new(
+                        color(top, color_space),
+                        color(bottom, color_space),
+                    ))
+                }
+            };
+            material_handles.insert(name.clone(), handle);
+        }
+
+        let find_material = |what: &str, name: &str| -> Result<MaterialHandle, String> {
+            material_handles.get(name).copied().ok_or_else(|| {
+                format!(
+                    "{}: unknown material \"{}\" (known materials: {})",
+                    what,
+                    name,
+                    material_handles
+                        .keys()
+                        .map(|k| format!("\"{}\"", k))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+        };
+
+        for (i, hitable) in self.hitables.iter().enumerate() {
+            match hitable {
+                HitableDescription::Sphere {
+                    center,
+                    radius,
+                    material,
+                } => {
+                    let what = format!("hitable {} (Sphere)", i);
+                    check_positive(&what, "radius", *radius)?;
+                    let material = find_material(&what, material)?;
+                    hitables.push(Sphere::new(vec3(*center), *radius, material));
+                }
+                HitableDescription::TracedSDF {
+                    sdf,
+                    material,
+                    detail_scale,
+                } => {
+                    let what = format!("hitable {} (TracedSDF)", i);
+                    let material = find_material(&what, material)?;
+                    let detail_scale = detail_scale.unwrap_or(settings.sdf_detail_scale);
+                    check_positive(&what, "detail_scale", detail_scale)?;
+                    match *sdf {
+                        SdfDescription::MandelBox {
+                            iterations,
+                            box_fold,
+                            sphere_fold: (min_radius, fixed_radius),
+                            scale,
+                        } => {
+                            if iterations == 0 {
+                                return Err(format!(
+                                    "{}: MandelBox iterations must be at least 1",
+                                    what
+                                ));
+                            }
+                            check_positive(&what, "sphere_fold min radius", min_radius)?;
+                            check_positive(&what, "sphere_fold fixed radius", fixed_radius)?;
+                            hitables.push(TracedSDF::new(
+                                MandelBox::new(
+                                    iterations,
+                                    BoxFold::new(box_fold),
+                                    SphereFold::new(min_radius, fixed_radius),
+                                    scale,
+                                ),
+                                detail_scale,
+                                material,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (i, light) in self.lights.iter().enumerate() {
+            match *light {
+                LightDescription::SphereLight {
+                    position,
+                    radius,
+                    emission,
+                    intensity,
+                    color_space,
+                } => {
+                    let what = format!("light {} (SphereLight)", i);
+                    check_positive(&what, "radius", radius)?;
+                    check_color(&what, "emission", emission)?;
+                    lights.push(Box::new(SphereLight::new(
+                        vec3(position),
+                        radius,
+                        color(emission, color_space) * intensity,
+                    )));
+                }
+            }
+        }
+
+        let res = Vec2::new(settings.resolution.0 as f32, settings.resolution.1 as f32);
+
+        let camera: Box<dyn Camera> = match self.camera {
+            CameraDescription::Pinhole {
+                vfov,
+                origin,
+                at,
+                up,
+            } => {
+                check_positive("camera", "vfov", vfov)?;
+                Box::new(PinholeCamera::new(
+                    res,
+                    vfov,
+                    vec3(origin),
+                    vec3(at),
+                    vec3(up),
+                ))
+            }
+            CameraDescription::ThinLens {
+                vfov,
+                aperture,
+                origin,
+                at,
+                up,
+                focus,
+            } => {
+                check_positive("camera", "vfov", vfov)?;
+                if aperture < 0.0 || aperture.is_nan() {
+                    return Err(format!(
+                        "camera: aperture must not be negative, got {}",
+                        aperture
+                    ));
+                }
+                Box::new(ThinLensCamera::new(
+                    res,
+                    vfov,
+                    f32x4::from(aperture),
+                    vec3(origin),
+                    vec3(at),
+                    vec3(up),
+                    vec3(focus),
+                ))
+            }
+            CameraDescription::Orthographic {
+                vertical_size,
+                origin,
+                at,
+                up,
+            } => {
+                check_positive("camera", "vertical_size", vertical_size)?;
+                Box::new(OrthographicCamera::new(
+                    res,
+                    vertical_size,
+                    vec3(origin),
+                    vec3(at),
+                    vec3(up),
+                ))
+            }
+        };
+
+        let mut cameras = CameraStore::new();
+        let camera = cameras.add_camera(camera);
+
+        let extinction_color = match self.volume.extinction_color {
+            Some(extinction_color) => {
+                check_color("volume", "extinction_color", extinction_color)?;
+                color(extinction_color, None)
+            }
+            None => Srgb::one(),
+        };
+        let volume_params = VolumeParams {
+            coeff_scattering: self.volume.scattering,
+            coeff_extinction: self.volume.extinction,
+            extinction_color,
+        };
+
+        Ok(Scene {
+            camera,
+            world: World {
+                materials,
+                hitables,
+                lights,
+                cameras,
+                volume_params,
+                radius: settings.world_radius,
+            },
+            settings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = r#"Scene(
+        settings: (
+            resolution: (64, 32),
+            samples: 1,
+            max_bounces: 2,
+            sdf_detail_scale: 0.5,
+            world_radius: 10.0,
+        ),
+        materials: {
+            "grey": Dielectric(albedo: (0.5, 0.5, 0.5), roughness: 0.5),
+        },
+        hitables: [
+            Sphere(center: (0.0, 0.0, 0.0), radius: 1.0, material: "grey"),
+        ],
+        lights: [
+            SphereLight(position: (0.0, 3.0, 0.0), radius: 0.5, emission: (1.0, 1.0, 1.0)),
+        ],
+        camera: Pinhole(
+            vfov: 45.0,
+            origin: (0.0, 0.0, 5.0),
+            at: (0.0, 0.0, 0.0),
+            up: (0.0, 1.0, 0.0),
+        ),
+    )"#;
+
+    fn build(source: &str) -> Result<Scene, String> {
+        ron::de::from_str::<SceneDescription>(source)
+            .map_err(|e| e.to_string())?
+            .build()
+    }
+
+    /// The error building `SCENE` with `from` replaced by `to` fails with.
+    fn error_with(from: &str, to: &str) -> String {
+        assert!(
+            SCENE.contains(from),
+            "the test scene doesn't contain {}",
+            from
+        );
+        match build(&SCENE.replace(from, to)) {
+            Ok(_) => panic!("replacing {} with {} was accepted", from, to),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn shipped_scenes_build() {
+        let scenes = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for name in ["mandelbox.ron", "mandelbox_clear.ron"].iter() {
+            if let Err(e) = load(scenes.join(name)) {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn valid_scenes_build() {
+        let scene = build(SCENE).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(scene.settings.resolution, (64, 32));
+        assert_eq!(scene.world.lights.len(), 1);
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        assert!(error_with("(64, 32)", "(64, 0)").contains("resolution must be non-zero"));
+        assert!(error_with("samples: 1", "samples: 0").contains("samples must be at least 1"));
+        assert!(error_with("world_radius: 10.0", "world_radius: -10.0")
+            .contains("world_radius must be positive"));
+        // Misspelled settings would otherwise silently fall back to their defaults.
+        assert!(error_with("samples: 1", "samples: 1, spectrall: true").contains("spectrall"));
+    }
+
+    #[test]
+    fn invalid_materials_are_rejected() {
+        assert!(
+            error_with("albedo: (0.5, 0.5, 0.5)", "albedo: (0.5, -0.5, 0.5)")
+                .contains("material \"grey\": albedo must not have negative")
+        );
+        assert!(error_with("roughness: 0.5", "roughness: 1.5")
+            .contains("roughness must be between 0.0 and 1.0"));
+    }
+
+    #[test]
+    fn unknown_materials_are_rejected() {
+        assert!(error_with("material: \"grey\"", "material: \"gray\"")
+            .contains("hitable 0 (Sphere): unknown material \"gray\" (known materials: \"grey\")"));
+    }
+
+    #[test]
+    fn invalid_shapes_are_rejected() {
+        assert!(error_with("radius: 1.0", "radius: 0.0")
+            .contains("hitable 0 (Sphere): radius must be positive"));
+        assert!(error_with("radius: 0.5", "radius: -0.5")
+            .contains("light 0 (SphereLight): radius must be positive"));
+        assert!(error_with("vfov: 45.0", "vfov: 0.0").contains("camera: vfov must be positive"));
+    }
+}
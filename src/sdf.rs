@@ -1,22 +1,83 @@
-use crate::hitable::{Hitable, WHit, WShadingPoint};
+use crate::hitable::{Hitable, WHit, WShadingPoint, WTraceStats};
 use crate::material::MaterialHandle;
 use crate::math::{f32x4, Wec3};
 use crate::ray::WRay;
-use crate::setup::SDF_DETAIL_SCALE;
 
 use sdfu::*;
 
-const MAX_MARCHES: u32 = 256;
+/// The most steps `TracedSDF` takes along a ray looking for a hit before it gives up and takes
+/// wherever it got to as the hit.
+pub const MAX_MARCHES: u32 = 256;
 const MAX_VIS_MARCHES: u32 = 100;
 
 pub struct TracedSDF<S> {
     sdf: S,
+    // Closer to 0 = smaller detail will be shown. Larger means less detail.
+    detail_scale: f32,
     material: MaterialHandle,
 }
 
 impl<S> TracedSDF<S> {
-    pub fn new(sdf: S, material: MaterialHandle) -> Self {
-        TracedSDF { sdf, material }
+    pub fn new(sdf: S, detail_scale: f32, material: MaterialHandle) -> Self {
+        TracedSDF {
+            sdf,
+            detail_scale,
+            material,
+        }
+    }
+}
+
+impl<S: SDF<f32x4, Wec3>> TracedSDF<S> {
+    /// Sphere trace `ray` until it is close enough to the surface to count as a hit, passes
+    /// `t_max`, or runs out of marches.
+    #[inline]
+    fn march(
+        &self,
+        ray: &WRay,
+        t_max: f32x4,
+        hit_threshold_at: &dyn Fn(f32x4) -> f32x4,
+    ) -> WTraceStats {
+        let dist = self.sdf.dist(ray.origin);
+        let mut t = dist;
+
+        let nan_mask = t.cmp_nan(t);
+
+        let mut steps = f32x4::ZERO;
+        let mut surface_dist = dist.abs();
+        let mut hit_mask = f32x4::ZERO;
+        let mut done_mask = nan_mask;
+
+        for _march in 0..MAX_MARCHES {
+            let point = ray.point_at(t);
+            let dist = self.sdf.dist(point);
+
+            steps = f32x4::merge(done_mask, steps, steps + f32x4::ONE);
+            surface_dist = f32x4::merge(done_mask, surface_dist, dist.abs());
+
+            hit_mask = dist.abs().cmp_lt(
+                f32x4::from(0.00005 * self.detail_scale)
+                    .max(f32x4::from(0.05 * self.detail_scale) * hit_threshold_at(t)),
+            );
+
+            let gt_mask = t.cmp_gt(t_max);
+            let hit_gt_nan_mask = hit_mask | nan_mask | gt_mask;
+            done_mask = hit_gt_nan_mask;
+
+            t = f32x4::merge(hit_gt_nan_mask, t, t + dist);
+
+            if hit_gt_nan_mask.move_mask() == 0b1111 {
+                break;
+            }
+        }
+
+        WTraceStats {
+            t,
+            steps,
+            surface_dist,
+            converged: hit_mask & !nan_mask,
+            exhausted: !done_mask,
+            nan: t.cmp_nan(t),
+        }
     }
 }
 
@@ -45,7 +106,7 @@ impl<S: SDF<f32x4, Wec3> + Send + Sync> Hitable for TracedSDF<S> {
             let point = dir.mul_add(Wec3::broadcast(t), start);
             let dist = self.sdf.dist(point);
 
-            hit_mask = dist.abs().cmp_lt(f32x4::from(0.0001 * SDF_DETAIL_SCALE).max(f32x4::from(0.00001 * SDF_DETAIL_SCALE) * t));
+            hit_mask = dist.abs().cmp_lt(f32x4::from(0.0001 * self.detail_scale).max(f32x4::from(0.00001 * self.detail_scale) * t));
 
             let hit_gt_nan_mask = hit_mask | gt_nan_mask;
             if hit_gt_nan_mask.move_mask() == 0b1111 {
@@ -57,29 +118,16 @@ impl<S: SDF<f32x4, Wec3> + Send + Sync> Hitable for TracedSDF<S> {
     }
 
     fn hit(&self, ray: &WRay, t_max: f32x4, hit_threshold_at: &dyn Fn(f32x4) -> f32x4) -> f32x4 {
-        let dist = self.sdf.dist(ray.origin);
-        let mut t = dist;
-
-        let nan_mask = t.cmp_nan(t);
-
-        for _march in 0..MAX_MARCHES {
-            let point = ray.point_at(t);
-            let dist = self.sdf.dist(point);
-
-            let hit_mask = dist.abs().cmp_lt(
-                f32x4::from(0.00005 * SDF_DETAIL_SCALE)
-                    .max(f32x4::from(0.05 * SDF_DETAIL_SCALE) * hit_threshold_at(t)));
-
-            let gt_mask = t.cmp_gt(t_max);
-            let hit_gt_nan_mask = hit_mask | nan_mask | gt_mask;
-
-            t = f32x4::merge(hit_gt_nan_mask, t, t + dist);
+        self.march(ray, t_max, hit_threshold_at).t
+    }
 
-            if hit_gt_nan_mask.move_mask() == 0b1111 {
-                break;
-            }
-        }
-        t
+    fn trace_stats(
+        &self,
+        ray: &WRay,
+        t_max: f32x4,
+        hit_threshold_at: &dyn Fn(f32x4) -> f32x4,
+    ) -> WTraceStats {
+        self.march(ray, t_max, hit_threshold_at)
     }
 
     fn get_shading_info(
@@ -89,7 +137,7 @@ impl<S: SDF<f32x4, Wec3> + Send + Sync> Hitable for TracedSDF<S> {
     ) -> (MaterialHandle, WShadingPoint) {
         let point = hit.point();
 
-        let half_pixel_size = f32x4::from(0.0001).max(f32x4::from(SDF_DETAIL_SCALE) * half_pixel_size_at(hit.t));
+        let half_pixel_size = f32x4::from(0.0001).max(f32x4::from(self.detail_scale) * half_pixel_size_at(hit.t));
 
         let normals = self.sdf.normals_fast(half_pixel_size);
 
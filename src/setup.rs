@@ -1,7 +1,9 @@
-use crate::{
-    camera::{CameraStore, CameraHandle, PinholeCamera},
+use rayn::{
+    camera::{CameraStore, PinholeCamera},
+    color::ColorSpace,
     light::{SphereLight, Light},
     world::World,
+    scene::{RenderSettings, Scene},
     material::MaterialStore,
     hitable::HitableStore,
     volume::VolumeParams,
@@ -43,11 +45,27 @@ pub const SDF_DETAIL_SCALE: f32 = 0.5;
 // use a lower (more detailed) SDF_DETAIL_SCALE.
 pub const FRACTAL_ITERATIONS: usize = 12;
 
-pub fn setup() -> (CameraHandle, World) {
+pub fn default_settings() -> RenderSettings {
+    RenderSettings {
+        resolution: (RESOLUTION.w, RESOLUTION.h),
+        samples: SAMPLES,
+        max_bounces: MAX_INDIRECT_BOUNCES,
+        volume_marches: VOLUME_MARCHES_PER_SAMPLE,
+        sdf_detail_scale: SDF_DETAIL_SCALE,
+        world_radius: WORLD_RADIUS,
+        working_space: ColorSpace::LinearSrgb,
+        spectral: false,
+    }
+}
+
+pub fn setup(settings: RenderSettings) -> Scene {
     let mut materials = MaterialStore::new();
     let mut hitables = HitableStore::new();
     let mut lights: Vec<Box<dyn Light>> = Vec::new();
 
+    // The colors below are in linear sRGB, so convert them to whatever space we're rendering in.
+    let rgb = |r, g, b| Srgb(ColorSpace::LinearSrgb.convert(Vec3::new(r, g, b), settings.working_space));
+
     // VOLUMETRICS
     // Volumetrics are very cool but are really expensive to render. You can change
     // these values or change them to None to disable that kind of volumetric effect.
@@ -57,6 +75,7 @@ pub fn setup() -> (CameraHandle, World) {
         // coeff_extinction: None,
         coeff_scattering: Some(0.25),
         coeff_extinction: Some(0.035),
+        extinction_color: Srgb::one(),
     };
 
     // SKY
@@ -64,16 +83,16 @@ pub fn setup() -> (CameraHandle, World) {
         // You can change the following numbers to change the color of the sky. The first Srgb color is the
         // color of the top of the skydome while the second number is the color of the bottom... they will be
         // smoothly blended together towards the middle.
-        Srgb::new(0.3, 0.4, 0.6),
-        Srgb::new(0.2, 0.3, 0.6) * 0.05,
+        rgb(0.3, 0.4, 0.6),
+        rgb(0.2, 0.3, 0.6) * 0.05,
     ));
 
-    hitables.push(Sphere::new(Vec3::new(0.0, 0.0, 0.0), WORLD_RADIUS, sky));
+    hitables.push(Sphere::new(Vec3::new(0.0, 0.0, 0.0), settings.world_radius, sky));
 
     // FRACTAL
     // Here you can change the material properties for the fractal. Try changing the Srgb color and the roughness, 
     // which should stay between 0.0 (completely smooth) and 1.0 (completely rough).
-    let grey = materials.add_material(Dielectric::new_remap(Srgb::new(0.2, 0.2, 0.2), 0.6));
+    let grey = materials.add_material(Dielectric::new_remap(rgb(0.2, 0.2, 0.2), 0.6));
 
     hitables.push(TracedSDF::new(
         // Try playing around with these numbers, which will dramatically affect how the fractal in the middle looks! The commented line
@@ -82,6 +101,7 @@ pub fn setup() -> (CameraHandle, World) {
         // to get a good look!
         // MandelBox::new(FRACTAL_ITERATIONS, BoxFold::new(1.0), SphereFold::new(0.5, 1.0), -2.0)
         MandelBox::new(FRACTAL_ITERATIONS, BoxFold::new(1.0), SphereFold::new(0.01, 1.9), -2.1),
+        settings.sdf_detail_scale,
         grey,
     ));
 
@@ -97,8 +117,8 @@ pub fn setup() -> (CameraHandle, World) {
 
     // OTHER LIGHTS
     // Try playing with the colors below to change the colors of the lights.
-    let green = Srgb::new(1.5, 4.5, 3.0).normalized();
-    let blue = Srgb::new(1.5, 3.0, 4.5).normalized();
+    let green = rgb(1.5, 4.5, 3.0).normalized();
+    let blue = rgb(1.5, 3.0, 4.5).normalized();
     let blue_emissive = materials.add_material(Emissive::new_splat(blue * 3.0));
     let green_emissive = materials.add_material(Emissive::new_splat(green * 3.0));
 
@@ -122,7 +142,7 @@ pub fn setup() -> (CameraHandle, World) {
     hitables.push(Sphere::new(Vec3::zero(), 0.24, green_emissive));
 
     // CAMERA
-    let res = Vec2::new(RESOLUTION.w as f32, RESOLUTION.h as f32);
+    let res = Vec2::new(settings.resolution.0 as f32, settings.resolution.1 as f32);
 
     // Here you can play with the settings of the camera! You should leave the 'res'
     // alone, but feel free to play with the FOV, origin, etc.
@@ -157,14 +177,16 @@ pub fn setup() -> (CameraHandle, World) {
 
     let camera = cameras.add_camera(Box::new(camera));
 
-    (
+    Scene {
         camera,
-        World {
+        world: World {
             materials,
             hitables,
             lights,
             cameras,
             volume_params,
+            radius: settings.world_radius,
         },
-    )
+        settings,
+    }
 }
\ No newline at end of file
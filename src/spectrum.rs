@@ -194,3 +194,101 @@ macro_rules! impl_wrapper_ops {
 
 impl_wrapper_ops!(Srgb => f32);
 impl_wrapper_ops!(WSrgb => f32x4);
+
+/// The range of wavelengths in nanometers sampled when rendering spectrally. Outside of it the eye
+/// is too insensitive for light to matter.
+pub const WAVELENGTH_MIN: f32 = 380.0;
+pub const WAVELENGTH_MAX: f32 = 730.0;
+
+/// Sample the three wavelengths each lane carries when rendering spectrally, given a uniform
+/// sample `u` per lane. A hero wavelength is chosen uniformly and the other two are spaced evenly
+/// after it, wrapping around the range, so each path estimates three wavelengths spread across
+/// the spectrum ("Hero Wavelength Spectral Sampling", Wilkie et al. 2014).
+pub fn sample_wavelengths(u: f32x4) -> Wec3 {
+    let range = f32x4::from(WAVELENGTH_MAX - WAVELENGTH_MIN);
+    let rotated = |offset: f32| {
+        let u = u + f32x4::from(offset);
+        f32x4::from(WAVELENGTH_MIN) + (u - u.floor()) * range
+    };
+    Wec3::new(rotated(0.0), rotated(1.0 / 3.0), rotated(2.0 / 3.0))
+}
+
+/// The width in nanometers of the transitions between the basis spectra. They're wide enough that
+/// neighbouring basis spectra overlap over most of their range, like the spectra of real
+/// reflectances, so light reflected by several colored surfaces or filtered by a colored volume
+/// isn't simply the product of their RGB colors.
+const BASIS_WIDTH: f32 = 20.0;
+
+/// The red, green and blue basis spectra RGB colors are uplifted with, at wavelengths `lambda`.
+/// They are sigmoids which are non-negative and sum to one, so white uplifts to a flat spectrum
+/// and reflectances stay in `[0, 1]`, and their colors are roughly those of the sRGB primaries.
+fn basis(lambda: f32x4) -> [f32x4; 3] {
+    let step_up = |center: f32| {
+        f32x4::ONE
+            / (f32x4::ONE + ((f32x4::from(center) - lambda) / f32x4::from(BASIS_WIDTH)).exp())
+    };
+    let above_blue = step_up(490.0);
+    let red = step_up(590.0);
+    [red, above_blue - red, f32x4::ONE - above_blue]
+}
+
+/// Uplift an RGB color to its spectrum's values at the given wavelengths. The uplift is linear, so
+/// sums and scalings of colors uplift to the same sums and scalings of their spectra.
+pub fn uplift(rgb: WSrgb, wavelengths: Wec3) -> WSrgb {
+    let at = |lambda: f32x4| {
+        let [r, g, b] = basis(lambda);
+        rgb.x * r + rgb.y * g + rgb.z * b
+    };
+    WSrgb(Wec3::new(
+        at(wavelengths.x),
+        at(wavelengths.y),
+        at(wavelengths.z),
+    ))
+}
+
+/// The CIE 1931 2° color matching functions at wavelength `lambda`, using the multi-lobe fit from
+/// "Simple Analytic Approximations to the CIE XYZ Color Matching Functions" (Wyman et al. 2013).
+fn cie_xyz(lambda: f32) -> Vec3 {
+    let lobe = |mu: f32, sigma_below: f32, sigma_above: f32| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+/// The matrix from CIE XYZ to RGB relative to the basis spectra, i.e. the inverse of the matrix
+/// whose columns are the XYZ of the basis spectra over the sampled range, stored row by row.
+/// Converting with it rather than to the working space's primaries means that uplifted colors come
+/// back out exactly, so scenes look the same whether they're rendered spectrally or not.
+const XYZ_TO_BASIS_RGB: [[f32; 3]; 3] = [
+    [0.0324574, -0.0190663, -0.00399497],
+    [-0.0160659, 0.0253452, 0.0000509103],
+    [0.00364823, -0.00586543, 0.0115854],
+];
+
+/// Convert radiance sampled at the given wavelengths (see `sample_wavelengths`) into an estimate
+/// of its RGB color.
+pub fn spectral_to_rgb(values: Srgb, wavelengths: Vec3) -> Srgb {
+    // Each wavelength was sampled with a pdf of 1 / range, and there are three of them.
+    let scale = (WAVELENGTH_MAX - WAVELENGTH_MIN) / 3.0;
+    let xyz = (cie_xyz(wavelengths.x) * values.x
+        + cie_xyz(wavelengths.y) * values.y
+        + cie_xyz(wavelengths.z) * values.z)
+        * scale;
+    let row = |r: [f32; 3]| r[0] * xyz.x + r[1] * xyz.y + r[2] * xyz.z;
+    Srgb::new(
+        row(XYZ_TO_BASIS_RGB[0]),
+        row(XYZ_TO_BASIS_RGB[1]),
+        row(XYZ_TO_BASIS_RGB[2]),
+    )
+}
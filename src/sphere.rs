@@ -84,4 +84,10 @@ impl<TR: WSequenced<Wec3>> Hitable for Sphere<TR> {
             WShadingPoint::new(hit, point, f32x4::ZERO, normal),
         )
     }
+
+    fn move_point(&self, point: Wec3, from: f32x4, to: f32x4) -> Wec3 {
+        let from = WSequenced::sample_at(&self.transform_seq, from);
+        let to = WSequenced::sample_at(&self.transform_seq, to);
+        point - from + to
+    }
 }
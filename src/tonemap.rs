@@ -0,0 +1,181 @@
+//! Display transforms used when writing low dynamic range images: an exposure adjustment, a tone
+//! mapping operator which compresses HDR values into `[0, 1]`, and an output transform which
+//! encodes them for a display.
+
+use crate::color::{ColorSpace, OutputTransform};
+use crate::math::{Mat3, Vec3};
+use crate::spectrum::Srgb;
+
+/// An operator which maps linear, unbounded scene values to linear display values in `[0, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapOperator {
+    /// Hard clip to `[0, 1]`. This is how images were written before tone mapping existed.
+    Clamp,
+    /// `x / (1 + x)` per channel.
+    Reinhard,
+    /// Reinhard with a white point: values at or above `white` map to 1.
+    ExtendedReinhard { white: f32 },
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+    /// Stephen Hill's fit of the ACES RRT and sRGB ODT.
+    AcesFitted,
+    /// A minimal polynomial approximation of Troy Sobotka's AgX.
+    Agx,
+}
+
+impl ToneMapOperator {
+    pub const NAMES: &'static [&'static str] = &[
+        "clamp",
+        "reinhard",
+        "reinhard-extended",
+        "hable",
+        "aces",
+        "agx",
+    ];
+
+    /// The white point used by the extended Reinhard operator when it is looked up by name.
+    pub const DEFAULT_WHITE: f32 = 4.0;
+
+    /// Looks up an operator by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "clamp" => Some(ToneMapOperator::Clamp),
+            "reinhard" => Some(ToneMapOperator::Reinhard),
+            "reinhard-extended" => Some(ToneMapOperator::ExtendedReinhard {
+                white: Self::DEFAULT_WHITE,
+            }),
+            "hable" => Some(ToneMapOperator::Hable),
+            "aces" => Some(ToneMapOperator::AcesFitted),
+            "agx" => Some(ToneMapOperator::Agx),
+            _ => None,
+        }
+    }
+
+    /// Map a linear color to linear display values in `[0, 1]`.
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        let color = color.map(|x| x.max(0.0));
+        let mapped = match *self {
+            ToneMapOperator::Clamp => color,
+            ToneMapOperator::Reinhard => color.map(|x| x / (1.0 + x)),
+            ToneMapOperator::ExtendedReinhard { white } => {
+                let white_sq = white * white;
+                color.map(|x| x * (1.0 + x / white_sq) / (1.0 + x))
+            }
+            ToneMapOperator::Hable => {
+                const EXPOSURE_BIAS: f32 = 2.0;
+                const WHITE: f32 = 11.2;
+                let white_scale = 1.0 / hable_partial(WHITE);
+                color.map(|x| hable_partial(x * EXPOSURE_BIAS) * white_scale)
+            }
+            ToneMapOperator::AcesFitted => aces_fitted(color),
+            ToneMapOperator::Agx => agx(color),
+        };
+        mapped.map(|x| x.max(0.0).min(1.0))
+    }
+}
+
+fn hable_partial(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+fn aces_fitted(color: Vec3) -> Vec3 {
+    // sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+    let input = Mat3::new(
+        Vec3::new(0.59719, 0.07600, 0.02840),
+        Vec3::new(0.35458, 0.90834, 0.13383),
+        Vec3::new(0.04823, 0.01566, 0.83777),
+    );
+    // ODT_SAT => XYZ => D60_2_D65 => sRGB
+    let output = Mat3::new(
+        Vec3::new(1.60475, -0.10208, -0.00327),
+        Vec3::new(-0.53108, 1.10813, -0.07276),
+        Vec3::new(-0.07367, -0.00605, 1.07602),
+    );
+
+    let v = input * color;
+    let v = v.map(|x| {
+        (x * (x + 0.024_578_6) - 0.000_090_537) / (x * (0.983_729 * x + 0.432_951) + 0.238_081)
+    });
+    output * v
+}
+
+fn agx(color: Vec3) -> Vec3 {
+    const MIN_EV: f32 = -12.4739;
+    const MAX_EV: f32 = 4.02607;
+
+    let inset = Mat3::new(
+        Vec3::new(0.842479, 0.0423282, 0.0423757),
+        Vec3::new(0.0784336, 0.878469, 0.0784336),
+        Vec3::new(0.0792237, 0.0791661, 0.879143),
+    );
+    let outset = Mat3::new(
+        Vec3::new(1.19688, -0.0528969, -0.0529716),
+        Vec3::new(-0.0980209, 1.15190, -0.0980435),
+        Vec3::new(-0.0990297, -0.0989612, 1.15107),
+    );
+
+    // Encode into a log space between the min and max exposures, then apply the contrast curve.
+    let v = inset * color;
+    let v = v.map(|x| {
+        let x = (x.max(1e-10).log2().max(MIN_EV).min(MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+
+    // The curve's output is display encoded, so decode it back to linear.
+    let v = outset * v;
+    v.map(|x| x.max(0.0).powf(2.2))
+}
+
+/// The piecewise sRGB opto-electronic transfer function, mapping linear values in `[0, 1]` to
+/// display encoded values.
+pub fn srgb_oetf(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The transform applied to color channels when they are written as low dynamic range images.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayTransform {
+    /// Exposure adjustment in stops; colors are scaled by `2^exposure` before tone mapping.
+    pub exposure: f32,
+    /// The tone mapping operator, which is applied in the output's color space and skipped for HDR
+    /// outputs. The ACES and AgX fits were made for sRGB primaries, so they are only
+    /// approximations for wider gamut outputs.
+    pub operator: ToneMapOperator,
+    pub output: OutputTransform,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        DisplayTransform {
+            exposure: 0.0,
+            operator: ToneMapOperator::Clamp,
+            output: OutputTransform::Srgb,
+        }
+    }
+}
+
+impl DisplayTransform {
+    /// Map a linear scene color in `space` to display values in `[0, 1]`, encoded for the output.
+    pub fn apply(&self, color: Srgb, space: ColorSpace) -> Srgb {
+        let exposed = space.convert(color.0 * 2f32.powf(self.exposure), self.output.space());
+        let mapped = if self.output.is_hdr() {
+            exposed
+        } else {
+            self.operator.apply(exposed)
+        };
+        Srgb(self.output.encode(mapped))
+    }
+}
@@ -1,5 +1,12 @@
+use crate::spectrum::Srgb;
+
 #[derive(Clone, Copy)]
 pub struct VolumeParams {
     pub coeff_scattering: Option<f32>,
     pub coeff_extinction: Option<f32>,
+    /// Scales `coeff_extinction` for each color of light, so colored volumes like haze which dims
+    /// blue light more than red can be made. When rendering spectrally it's uplifted to a spectrum
+    /// and applied to each wavelength, which tints light passing through more of the volume more
+    /// deeply than applying it to RGB does.
+    pub extinction_color: Srgb,
 }
@@ -10,4 +10,6 @@ pub struct World {
     pub materials: MaterialStore,
     pub cameras: CameraStore,
     pub volume_params: VolumeParams,
+    /// Rays which travel further than twice this distance are considered to have escaped.
+    pub radius: f32,
 }